@@ -2,14 +2,22 @@
 #![allow(dead_code)]
 #![allow(clippy::upper_case_acronyms)]
 
-#[derive(Debug)]
+use crate::message::Message;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CapMode {
     LS,
     END,
+    /// Capabilities to enable
+    REQ(Vec<String>),
+    /// Capabilities the server enabled
+    ACK(Vec<String>),
+    /// Capabilities the server refused
+    NAK(Vec<String>),
 }
 
 /// IRC commands
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
     // TODO:
     // SERVICE <nickname> <reserved> <distribution> <type> <reserved> <info>
@@ -39,6 +47,11 @@ pub enum Command {
     ),
     #[doc(hidden)]
     CAP(CapMode),
+    /// The server is closing the connection
+    ERROR(
+        /// Reason
+        String,
+    ),
     /// Invite user to channel
     /// ```no_run
     /// # use circe::*;
@@ -63,6 +76,21 @@ pub enum Command {
         /// Channel
         String,
     ),
+    /// Kicks a user from a channel
+    /// ```no_run
+    /// # use circe::*;
+    /// # let mut client = Client::new(Default::default())?;
+    /// client.kick("#main", "spammer", Some("no spam"))?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    KICK(
+        /// Channel
+        String,
+        /// User
+        String,
+        /// Reason
+        Option<String>,
+    ),
     /// Lists all channels and their topics
     /// ```no_run
     /// # use circe::*;
@@ -105,6 +133,26 @@ pub enum Command {
     ),
     #[doc(hidden)]
     NICK(String),
+    /// Sends a notice, clients must never reply to one automatically
+    /// ```no_run
+    /// # use circe::*;
+    /// # let mut client = Client::new(Default::default())?;
+    /// client.notice("#main", "This is an example notice")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    NOTICE(
+        /// Target
+        String,
+        /// Message
+        String,
+    ),
+    /// A numeric reply from the server, like 001 after a successful login
+    NUMERIC(
+        /// Code
+        u16,
+        /// Parameters, the first one is our nickname
+        Vec<String>,
+    ),
     /// Attempts to identify as a channel operator
     /// ```no_run
     /// # use circe::*;
@@ -181,38 +229,10 @@ pub enum Command {
 }
 
 impl Command {
-    /// Creates a Command from a `&str`. Currently only `[PING]` and `[PRIVMSG]` are supported.
-    ///
-    /// # Panics
-    ///
-    /// This function will panic if the ``IRCd`` sends malformed messages. Please contact the
-    /// maintainer of your ``IRCd`` if this happens.
+    /// Creates a Command from a `&str`, see [`Message::command`] for how lines are mapped.
+    /// Lines that can't be parsed at all are [`Command::OTHER`].
     #[must_use]
     pub fn command_from_str(s: &str) -> Self {
-        let new = s.trim();
-
-        let parts: Vec<&str> = new.split_whitespace().collect();
-
-        if parts.first() == Some(&"PING") {
-            // We can assume that [1] exists because if it doesn't then something's gone very wrong
-            // with the IRCD
-            let command = parts[1].to_string();
-            return Self::PONG(command);
-        } else if parts.get(1) == Some(&"PRIVMSG") {
-            let nick = parts[0];
-            let index = nick.chars().position(|c| c == '!').unwrap(); // This panics for the same reason as above
-            let nick = String::from(&nick[1..index]);
-            let target = parts[2];
-            let mut builder = String::new();
-
-            #[allow(clippy::unnecessary_to_owned)]
-            for part in parts[3..].to_vec() {
-                builder.push_str(&format!("{} ", part));
-            }
-            #[allow(clippy::needless_borrow)]
-            return Self::PRIVMSG(nick, target.to_string(), (&builder[1..]).to_string());
-        }
-
-        Self::OTHER(new.to_string())
+        Message::parse(s).map_or_else(|_| Self::OTHER(s.trim().to_string()), |m| m.command())
     }
 }
@@ -0,0 +1,59 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+/// Changes of the connection, see [`crate::Client::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Identified and joined the channels again after a reconnect.
+    Connected,
+    /// The stream ended or failed, or the server sent RECONNECT.
+    Disconnected,
+    /// Reconnecting failed, the next attempt follows after `retry_in`.
+    Reconnecting { attempt: u32, retry_in: Duration },
+    /// Every attempt failed, the client tries again the next time it is read.
+    GaveUp,
+}
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            attempts: 10,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay after the given failed attempt, starting at 1.
+    #[must_use]
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(self.max)
+    }
+}
+
+/// Whether a read or write failed because the connection is gone and has to be made again: the
+/// server closed or reset it, or TLS failed on it.
+#[must_use]
+pub fn is_lost(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::NotConnected
+            // rustls reports TLS errors as invalid data
+            | ErrorKind::InvalidData
+    )
+}
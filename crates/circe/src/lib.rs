@@ -32,32 +32,87 @@ use rustls::pki_types::ServerName;
 use rustls::ClientConnection;
 use rustls::StreamOwned;
 use std::borrow::Cow;
-use std::io::{Error, Read, Write};
+use std::io::{Error, ErrorKind, Read, Write};
 use std::net::Shutdown;
 use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// IRC comamnds
 pub mod commands;
+/// Reconnecting after the connection broke
+pub mod connection;
+/// IRC message parsing
+pub mod message;
+/// Keeping outgoing messages within the server's limits
+pub mod ratelimit;
+/// Splitting what the server sends into lines
+pub mod reader;
+#[cfg(test)]
+mod tests;
+
+/// What the client reads from and writes to, a TLS connection outside of the tests.
+trait Transport: Read + Write + Send {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error>;
+    fn shutdown(&self) -> Result<(), Error>;
+}
+
+impl Transport for StreamOwned<ClientConnection, TcpStream> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.sock.shutdown(Shutdown::Both)
+    }
+}
+
+#[cfg(test)]
+impl Transport for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
 
 /// An IRC client
 pub struct Client {
     config: Config,
-    stream: StreamOwned<ClientConnection, TcpStream>,
+    stream: Box<dyn Transport>,
+    lines: reader::LineBuffer,
+    capabilities: Vec<String>,
+    subscribers: Vec<Sender<connection::ConnectionState>>,
+    queue: ratelimit::SendQueue,
 }
 
 /// Config for the IRC client
 #[derive(Clone, Default)]
 pub struct Config {
+    /// IRCv3 capabilities to request while identifying, e.g. `twitch.tv/tags`
+    pub capabilities: Vec<String>,
     pub channels: Vec<String>,
     pub host: String,
     pub mode: Option<String>,
     pub nickname: Option<String>,
+    /// Sent as PASS before identifying, e.g. `oauth:...` on Twitch
+    pub password: Option<String>,
     pub port: u16,
+    /// Limits for commands sent with [`Client::enqueue`]
+    pub rate_limits: ratelimit::RateLimits,
+    /// How long [`Client::read_message`] waits for a line before it returns [`NoNewLines`] so the
+    /// caller can do something else, `None` waits until a line comes in
+    pub read_timeout: Option<Duration>,
+    /// Backoff between attempts when the connection has to be made again
+    pub reconnect: connection::Backoff,
     pub username: String,
 }
 
-/// Custom Error for the `read` function
+/// Custom Error for the `read` function, nothing came in before the read timed out or the
+/// connection is gone for good
 #[derive(Debug)]
 pub struct NoNewLines;
 
@@ -82,6 +137,28 @@ impl Client {
     /// # Panics
     /// Panics if the client can't connect to the given host.
     pub fn new(config: Config) -> Result<Self> {
+        let stream = Self::connect(&config)?;
+        Ok(Self::with_stream(config, Box::new(stream)))
+    }
+
+    fn with_stream(config: Config, stream: Box<dyn Transport>) -> Self {
+        Self {
+            queue: ratelimit::SendQueue::new(config.rate_limits.clone()),
+            config,
+            stream,
+            lines: reader::LineBuffer::default(),
+            capabilities: Vec::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// A client on a plain TCP connection, for talking to a fake server in the tests.
+    #[cfg(test)]
+    fn over_tcp(config: Config, stream: TcpStream) -> Self {
+        Self::with_stream(config, Box::new(stream))
+    }
+
+    fn connect(config: &Config) -> Result<StreamOwned<ClientConnection, TcpStream>> {
         let mut roots = rustls::RootCertStore::empty();
         for cert in rustls_native_certs::load_native_certs().expect("could not load platform certs")
         {
@@ -99,12 +176,11 @@ impl Client {
         let tcp_stream = TcpStream::connect(format!("{}:{}", config.host, config.port))?;
         let client = ClientConnection::new(Arc::new(tls_config), dns_name)?;
 
-        let stream = StreamOwned::new(client, tcp_stream);
-
-        Ok(Self { config, stream })
+        Ok(StreamOwned::new(client, tcp_stream))
     }
 
-    /// Identify user and joins the in the [`Config`] specified channels.
+    /// Identify user and joins the in the [`Config`] specified channels, the PASS is sent first
+    /// when there is one.
     /// ```no_run
     /// # use circe::*;
     /// # let mut client = Client::new(Default::default())?;
@@ -112,9 +188,18 @@ impl Client {
     /// # Ok::<(), std::io::Error>(())
     /// ```
     /// # Errors
-    /// Returns error if the client could not write to the stream.
+    /// Returns error if the client could not write to the stream or the connection closed before
+    /// the server welcomed us.
     pub fn identify(&mut self) -> Result<(), Error> {
+        if let Some(password) = self.config.password.clone() {
+            self.write_command(commands::Command::PASS(password))?;
+        }
         self.write_command(commands::Command::CAP(commands::CapMode::LS))?;
+        if !self.config.capabilities.is_empty() {
+            self.write_command(commands::Command::CAP(commands::CapMode::REQ(
+                self.config.capabilities.clone(),
+            )))?;
+        }
         self.write_command(commands::Command::CAP(commands::CapMode::END))?;
 
         self.write_command(commands::Command::USER(
@@ -130,19 +215,17 @@ impl Client {
             self.write_command(commands::Command::NICK(self.config.username.clone()))?;
         }
 
+        self.capabilities.clear();
         loop {
-            if let Ok(ref command) = self.read() {
-                match command {
-                    commands::Command::PING(code) => {
-                        self.write_command(commands::Command::PONG(code.to_string()))?;
-                    }
-                    commands::Command::OTHER(line) => {
-                        if line.contains("001") {
-                            break;
-                        }
-                    }
-                    _ => {}
+            match self.next_message()?.command() {
+                commands::Command::NUMERIC(1, _) => break,
+                commands::Command::CAP(commands::CapMode::ACK(capabilities)) => {
+                    self.capabilities.extend(capabilities);
                 }
+                commands::Command::CAP(commands::CapMode::NAK(capabilities)) => {
+                    log::warn!("Server refused capabilities {capabilities:?}");
+                }
+                _ => {}
             }
         }
 
@@ -155,24 +238,110 @@ impl Client {
         Ok(())
     }
 
-    // edit by me - i am pretty sure that original circe creator tested this and it doesn't matter
-    #[allow(clippy::unused_io_amount)]
-    fn read_string(&mut self) -> Option<String> {
-        let mut buffer = [0u8; 512];
+    /// Get a channel with every [`connection::ConnectionState`] change from now on.
+    pub fn subscribe(&mut self) -> Receiver<connection::ConnectionState> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
 
-        match self.stream.read(&mut buffer) {
-            Ok(_) => {}
-            Err(_) => return None,
-        };
+    fn emit(&mut self, state: connection::ConnectionState) {
+        self.subscribers.retain(|tx| tx.send(state.clone()).is_ok());
+    }
 
-        let res = String::from_utf8_lossy(&buffer);
+    /// Connect again, identify and join the channels, with the [`Config::reconnect`] backoff
+    /// between attempts. [`Client::read`] does this by itself when the connection breaks.
+    /// # Errors
+    /// Returns the last error once every attempt failed.
+    pub fn reconnect(&mut self) -> Result<()> {
+        use connection::ConnectionState;
 
-        // The trimming is required because if the message is less than 512 bytes it will be
-        // padded with a bunch of 0u8 because of the pre-allocated buffer
-        Some(res.trim().trim_matches(char::from(0)).trim().into())
+        self.stream.shutdown().ok();
+        self.emit(ConnectionState::Disconnected);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = Self::connect(&self.config).and_then(|stream| {
+                self.stream = Box::new(stream);
+                self.lines = reader::LineBuffer::default();
+                Ok(self.identify()?)
+            });
+            match result {
+                Ok(()) => {
+                    log::info!(
+                        "Reconnected to {} after {attempt} attempts",
+                        self.config.host
+                    );
+                    self.emit(ConnectionState::Connected);
+                    return Ok(());
+                }
+                Err(e) if attempt >= self.config.reconnect.attempts => {
+                    log::error!(
+                        "Giving up on {} after {attempt} attempts: {e}",
+                        self.config.host
+                    );
+                    self.emit(ConnectionState::GaveUp);
+                    return Err(e);
+                }
+                Err(e) => {
+                    let retry_in = self.config.reconnect.delay(attempt);
+                    log::warn!("Reconnecting attempt {attempt} failed, retry in {retry_in:?}: {e}");
+                    self.emit(ConnectionState::Reconnecting { attempt, retry_in });
+                    std::thread::sleep(retry_in);
+                }
+            }
+        }
     }
 
-    /// Read data coming from the IRC as a [`commands::Command`].
+    /// Next complete line from the server, reading more from the stream until there is one.
+    /// Lines over the length limits are logged and skipped.
+    fn read_string(&mut self) -> Result<String, Error> {
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            match self.lines.next_line() {
+                Some(Ok(line)) => return Ok(line),
+                Some(Err(e)) => {
+                    log::warn!("{e}");
+                    continue;
+                }
+                None => {}
+            }
+
+            match self.stream.read(&mut buffer)? {
+                0 => return Err(Error::from(std::io::ErrorKind::UnexpectedEof)),
+                read => self.lines.push(&buffer[..read]),
+            }
+        }
+    }
+
+    /// Next message without reconnecting, PINGs are answered and lines that don't parse skipped.
+    fn next_message(&mut self) -> Result<message::Message, Error> {
+        loop {
+            let line = self.read_string()?;
+            let message = match message::Message::parse(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    log::warn!("{e}: {line}");
+                    continue;
+                }
+            };
+
+            if message.command == "PING" {
+                let token = message
+                    .args()
+                    .first()
+                    .map_or_else(String::new, |t| (*t).to_string());
+                self.write_command(commands::Command::PONG(token))?;
+            }
+            return Ok(message);
+        }
+    }
+
+    /// Read data coming from the IRC as a [`commands::Command`], PINGs are answered before they
+    /// are returned. A broken connection or a RECONNECT from the server is reconnected, see
+    /// [`Client::reconnect`].
     /// ```no_run
     /// # use circe::*;
     /// # use circe::commands::Command;
@@ -188,22 +357,111 @@ impl Client {
     /// # }
     /// ```
     /// # Errors
-    /// Returns error if there are no new messages. This should not be taken as an actual error, because nothing went wrong.
+    /// Returns error if the connection broke and reconnecting failed, or no line came in within
+    /// [`Config::read_timeout`].
     pub fn read(&mut self) -> Result<commands::Command, NoNewLines> {
-        if let Some(string) = self.read_string() {
-            let command = commands::Command::command_from_str(&string);
+        self.read_message().map(|message| message.command())
+    }
 
-            if let commands::Command::PONG(command) = command {
-                if let Err(_e) = self.write_command(commands::Command::PONG(command)) {
+    /// Read the next [`message::Message`] with its tags, like [`Client::read`] PINGs are answered
+    /// before they are returned.
+    /// ```no_run
+    /// # use circe::*;
+    /// # use circe::commands::Command;
+    /// # let mut client = Client::new(Default::default())?;
+    /// if let Ok(message) = client.read_message() {
+    ///     if let Command::PRIVMSG(_, _, text) = message.command() {
+    ///         println!("{} (moderator: {}): {}", message.display_name(), message.is_moderator(), text);
+    ///     }
+    /// }
+    /// # Ok::<(), color_eyre::Report>(())
+    /// ```
+    /// # Errors
+    /// Returns error if the connection broke and reconnecting failed, or no line came in within
+    /// [`Config::read_timeout`].
+    pub fn read_message(&mut self) -> Result<message::Message, NoNewLines> {
+        let deadline = self
+            .config
+            .read_timeout
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            let ready_in = self.flush().unwrap_or_else(|e| {
+                log::warn!("Could not send queued commands: {e}");
+                None
+            });
+            // wake up for the next queued command or the caller, even when nothing comes in
+            let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let timeout = ready_in
+                .into_iter()
+                .chain(left)
+                .min()
+                .map(|timeout| timeout.max(Duration::from_millis(1)));
+            if let Err(e) = self.stream.set_read_timeout(timeout) {
+                log::warn!("Could not set the read timeout: {e}");
+            }
+            match self.next_message() {
+                // Twitch is about to restart the server and asks everyone to come back
+                Ok(message) if message.command == "RECONNECT" => {
+                    log::info!("Server asked to reconnect");
+                }
+                Ok(message) => return Ok(message),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                // nothing came in for a while, the connection is still fine
+                Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                    // our own timeout for the queue, unless the caller's is up too
+                    let expired = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                    if timeout.is_some() && !expired {
+                        continue;
+                    }
                     return Err(NoNewLines);
                 }
-                return Ok(commands::Command::PONG("".to_string()));
+                // the server closed or reset the connection, or TLS failed on it
+                Err(e) if connection::is_lost(&e) => {
+                    log::warn!("Lost connection to {}: {e}", self.config.host);
+                }
+                Err(e) => {
+                    log::warn!("Could not read from {}: {e}", self.config.host);
+                    return Err(NoNewLines);
+                }
+            }
+            if self.reconnect().is_err() {
+                return Err(NoNewLines);
             }
+        }
+    }
 
-            return Ok(command);
+    /// Queue a command to be sent within [`Config::rate_limits`], it returns right away. Queued
+    /// commands go out with [`Client::flush`], reads keep flushing while they wait for a line so
+    /// they don't wait for the server to say something first.
+    /// ```no_run
+    /// # use circe::*;
+    /// # use circe::commands::Command;
+    /// # let mut client = Client::new(Default::default())?;
+    /// client.enqueue(Command::PRIVMSG("".to_string(), "#main".to_string(), ":Hello".to_string()))?;
+    /// # Ok::<(), color_eyre::Report>(())
+    /// ```
+    /// # Errors
+    /// Returns error if the queue is full or the same message was just sent to the channel.
+    pub fn enqueue(&mut self, command: commands::Command) -> Result<(), ratelimit::QueueError> {
+        self.queue.push(command, Instant::now())
+    }
+
+    /// Send every queued command the rate limits allow right now, returns how long until the next
+    /// one may go or `None` when the queue is empty.
+    /// # Errors
+    /// Returns error if the client could not write to the stream, the command is lost then.
+    pub fn flush(&mut self) -> Result<Option<Duration>, Error> {
+        let now = Instant::now();
+        while let Some(command) = self.queue.pop_ready(now) {
+            self.write_command(command)?;
         }
+        Ok(self.queue.ready_in(now))
+    }
 
-        Err(NoNewLines)
+    /// Capabilities the server acknowledged while identifying.
+    #[must_use]
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
     }
 
     pub fn write(&mut self, data: &str) -> Result<(), Error> {
@@ -230,8 +488,8 @@ impl Client {
     /// Returns error if the client could not write to the stream.
     pub fn write_command(&mut self, command: commands::Command) -> Result<(), Error> {
         use commands::Command::{
-            ADMIN, AWAY, CAP, INVITE, JOIN, LIST, MODE, NAMES, NICK, OPER, OTHER, PART, PASS, PING,
-            PONG, PRIVMSG, QUIT, TOPIC, USER,
+            ADMIN, AWAY, CAP, ERROR, INVITE, JOIN, KICK, LIST, MODE, NAMES, NICK, NOTICE, NUMERIC,
+            OPER, OTHER, PART, PASS, PING, PONG, PRIVMSG, QUIT, TOPIC, USER,
         };
         let computed = match command {
             ADMIN(target) => {
@@ -243,11 +501,21 @@ impl Client {
                 Cow::Owned(formatted) as Cow<str>
             }
             CAP(mode) => {
-                use commands::CapMode::{END, LS};
-                Cow::Borrowed(match mode {
-                    LS => "CAP LS 302",
-                    END => "CAP END",
-                }) as Cow<str>
+                use commands::CapMode::{ACK, END, LS, NAK, REQ};
+                match mode {
+                    LS => Cow::Borrowed("CAP LS 302") as Cow<str>,
+                    END => Cow::Borrowed("CAP END") as Cow<str>,
+                    REQ(capabilities) => {
+                        let formatted = format!("CAP REQ :{}", capabilities.join(" "));
+                        Cow::Owned(formatted) as Cow<str>
+                    }
+                    ACK(_) | NAK(_) => {
+                        return Err(Error::other("Cannot write server CAP replies"));
+                    }
+                }
+            }
+            ERROR(_) | NUMERIC(..) => {
+                return Err(Error::other("Cannot write server replies"));
             }
             INVITE(username, channel) => {
                 let formatted = format!("INVITE {} {}", username, channel);
@@ -257,6 +525,16 @@ impl Client {
                 let formatted = format!("JOIN {}", channel);
                 Cow::Owned(formatted) as Cow<str>
             }
+            KICK(channel, user, reason) => {
+                let formatted = {
+                    if let Some(reason) = reason {
+                        format!("KICK {} {} :{}", channel, user, reason)
+                    } else {
+                        format!("KICK {} {}", channel, user)
+                    }
+                };
+                Cow::Owned(formatted) as Cow<str>
+            }
             LIST(channel, server) => {
                 let mut formatted = "LIST".to_string();
                 if let Some(channel) = channel {
@@ -291,6 +569,10 @@ impl Client {
                 };
                 Cow::Owned(formatted) as Cow<str>
             }
+            NOTICE(target, message) => {
+                let formatted = format!("NOTICE {} :{}", target, message);
+                Cow::Owned(formatted) as Cow<str>
+            }
             OPER(nick, password) => {
                 let formatted = format!("OPER {} {}", nick, password);
                 Cow::Owned(formatted) as Cow<str>
@@ -393,6 +675,23 @@ impl Client {
         Ok(())
     }
 
+    /// Helper function for sending NOTICEs, bots should use these for automatic messages
+    /// ```no_run
+    /// # use circe::*;
+    /// # let mut client = Client::new(Default::default())?;
+    /// client.notice("#main", "This is an example notice")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    /// # Errors
+    /// Returns error if the client could not write to the stream.
+    pub fn notice(&mut self, target: &str, message: &str) -> Result<(), Error> {
+        self.write_command(commands::Command::NOTICE(
+            target.to_string(),
+            message.to_string(),
+        ))?;
+        Ok(())
+    }
+
     /// Helper function to INVITE people to a channels
     /// ```no_run
     /// # use circe::*;
@@ -499,6 +798,24 @@ impl Client {
         Ok(())
     }
 
+    /// Helper function for kicking users from a channel.
+    /// ```no_run
+    /// # use circe::*;
+    /// # let mut client = Client::new(Default::default())?;
+    /// client.kick("#main", "spammer", Some("no spam"))?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    /// # Errors
+    /// Returns error if the client could not write to the stream.
+    pub fn kick(&mut self, channel: &str, user: &str, reason: Option<&str>) -> Result<(), Error> {
+        self.write_command(commands::Command::KICK(
+            channel.to_string(),
+            user.to_string(),
+            reason.map(str::to_string),
+        ))?;
+        Ok(())
+    }
+
     /// Helper function for leaving channels.
     /// ```no_run
     /// # use circe::*;
@@ -553,7 +870,7 @@ impl Client {
             )))?;
         }
 
-        self.stream.sock.shutdown(Shutdown::Both)?;
+        self.stream.shutdown()?;
 
         Ok(())
     }
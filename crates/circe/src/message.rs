@@ -0,0 +1,359 @@
+use crate::commands::{CapMode, Command};
+use std::collections::HashMap;
+
+/// Where a message came from, `nick` is the server name for messages from the server itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prefix {
+    pub nick: String,
+    pub user: Option<String>,
+    pub host: Option<String>,
+}
+
+impl Prefix {
+    /// Parse `nick!user@host`, `nick@host` or a bare server name.
+    #[must_use]
+    pub fn parse(prefix: &str) -> Self {
+        let (rest, host) = match prefix.split_once('@') {
+            Some((rest, host)) => (rest, Some(host.to_string())),
+            None => (prefix, None),
+        };
+        let (nick, user) = match rest.split_once('!') {
+            Some((nick, user)) => (nick, Some(user.to_string())),
+            None => (rest, None),
+        };
+        Self {
+            nick: nick.to_string(),
+            user,
+            host,
+        }
+    }
+}
+
+impl std::fmt::Display for Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.nick)?;
+        if let Some(user) = &self.user {
+            write!(f, "!{user}")?;
+        }
+        if let Some(host) = &self.host {
+            write!(f, "@{host}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a line could not be parsed into a [`Message`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Nothing but whitespace.
+    Empty,
+    /// Tags or a prefix without a command after them.
+    MissingCommand,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "Empty IRC line"),
+            ParseError::MissingCommand => write!(f, "IRC line without a command"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A single IRC line, `[@tags] [:prefix] <command> [params] [:trailing]`.
+///
+/// ```
+/// # use circe::message::Message;
+/// let message = Message::parse(":nick!user@host PRIVMSG #main :hello there")?;
+/// assert_eq!(message.command, "PRIVMSG");
+/// assert_eq!(message.params, ["#main"]);
+/// assert_eq!(message.trailing.as_deref(), Some("hello there"));
+/// # Ok::<(), circe::message::ParseError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    /// IRCv3 tags with their values unescaped, tags without a value map to an empty string.
+    pub tags: HashMap<String, String>,
+    pub prefix: Option<Prefix>,
+    /// Upper cased command, or the three digit code of a numeric reply.
+    pub command: String,
+    /// Middle parameters, without the trailing one.
+    pub params: Vec<String>,
+    /// The last parameter when it was sent after a `:`, it may contain spaces.
+    pub trailing: Option<String>,
+}
+
+/// Undo the IRCv3 tag value escaping, `\:` is `;`, `\s` a space and `\r`, `\n` and `\\` what
+/// they look like. Any other escaped character is itself and a trailing `\` is dropped.
+fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Middle parameters after which the rest of the line is the trailing parameter, see RFC 2812.
+const MAX_MIDDLE_PARAMS: usize = 14;
+
+impl Message {
+    /// Parse a line, with or without its `\r\n`.
+    ///
+    /// # Errors
+    /// Returns error if the line is empty or has no command.
+    pub fn parse(line: &str) -> Result<Self, ParseError> {
+        let mut rest = line.trim_end_matches(['\r', '\n']).trim_start_matches(' ');
+        if rest.trim().is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        let mut message = Message::default();
+        if let Some(tags) = rest.strip_prefix('@') {
+            let (tags, after) = tags.split_once(' ').unwrap_or((tags, ""));
+            for tag in tags.split(';').filter(|tag| !tag.is_empty()) {
+                let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                message
+                    .tags
+                    .insert(key.to_string(), unescape_tag_value(value));
+            }
+            rest = after.trim_start_matches(' ');
+        }
+
+        if let Some(prefix) = rest.strip_prefix(':') {
+            let (prefix, after) = prefix.split_once(' ').unwrap_or((prefix, ""));
+            message.prefix = Some(Prefix::parse(prefix));
+            rest = after.trim_start_matches(' ');
+        }
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return Err(ParseError::MissingCommand);
+        }
+        message.command = command.to_ascii_uppercase();
+
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(trailing) = rest.strip_prefix(':') {
+                message.trailing = Some(trailing.to_string());
+                break;
+            }
+            if message.params.len() == MAX_MIDDLE_PARAMS {
+                message.trailing = Some(rest.to_string());
+                break;
+            }
+            let (param, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            message.params.push(param.to_string());
+            rest = after;
+        }
+
+        Ok(message)
+    }
+
+    /// Nickname of the sender, empty for messages without a prefix.
+    #[must_use]
+    pub fn nick(&self) -> &str {
+        self.prefix
+            .as_ref()
+            .map_or("", |prefix| prefix.nick.as_str())
+    }
+
+    /// Value of a tag, `Some("")` for a tag without a value.
+    #[must_use]
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    /// Twitch badges as `(name, version)`, e.g. `("subscriber", "12")`.
+    #[must_use]
+    pub fn badges(&self) -> Vec<(&str, &str)> {
+        self.tag("badges")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|badge| !badge.is_empty())
+            .map(|badge| badge.split_once('/').unwrap_or((badge, "")))
+            .collect()
+    }
+
+    #[must_use]
+    pub fn has_badge(&self, name: &str) -> bool {
+        self.badges().iter().any(|(badge, _)| *badge == name)
+    }
+
+    /// Twitch display name, the nickname when the tag is missing or empty.
+    #[must_use]
+    pub fn display_name(&self) -> &str {
+        self.tag("display-name")
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| self.nick())
+    }
+
+    #[must_use]
+    pub fn is_broadcaster(&self) -> bool {
+        self.has_badge("broadcaster")
+    }
+
+    /// Moderators of the channel, the broadcaster counts as one.
+    #[must_use]
+    pub fn is_moderator(&self) -> bool {
+        self.tag("mod") == Some("1") || self.has_badge("moderator") || self.is_broadcaster()
+    }
+
+    /// Subscribers of the channel, founders were the first ones.
+    #[must_use]
+    pub fn is_subscriber(&self) -> bool {
+        self.tag("subscriber") == Some("1")
+            || self.has_badge("subscriber")
+            || self.has_badge("founder")
+    }
+
+    /// Bits cheered with this message, 0 for none.
+    #[must_use]
+    pub fn bits(&self) -> u32 {
+        self.tag("bits")
+            .and_then(|bits| bits.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// All parameters including the trailing one.
+    #[must_use]
+    pub fn args(&self) -> Vec<&str> {
+        self.params
+            .iter()
+            .map(String::as_str)
+            .chain(self.trailing.as_deref())
+            .collect()
+    }
+
+    /// The three digit code of a numeric reply.
+    #[must_use]
+    pub fn numeric(&self) -> Option<u16> {
+        if self.command.len() == 3 && self.command.bytes().all(|b| b.is_ascii_digit()) {
+            self.command.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    /// Map the message to a [`Command`], anything unknown or missing parameters is
+    /// [`Command::OTHER`] with the whole line.
+    #[must_use]
+    pub fn command(&self) -> Command {
+        let args = self.args();
+        let arg = |i: usize| args.get(i).map(|arg| (*arg).to_string());
+        let other = || Command::OTHER(self.to_string());
+        let list = |i: usize| {
+            args.get(i)
+                .map(|caps| caps.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+
+        if let Some(code) = self.numeric() {
+            return Command::NUMERIC(code, args.iter().map(|arg| (*arg).to_string()).collect());
+        }
+
+        let command = match self.command.as_str() {
+            "PING" => arg(0).map(Command::PING),
+            "PONG" => Some(Command::PONG(arg(1).or_else(|| arg(0)).unwrap_or_default())),
+            "PRIVMSG" => match (arg(0), arg(1)) {
+                (Some(target), Some(text)) => {
+                    Some(Command::PRIVMSG(self.nick().to_string(), target, text))
+                }
+                _ => None,
+            },
+            "NOTICE" => match (arg(0), arg(1)) {
+                (Some(target), Some(text)) => Some(Command::NOTICE(target, text)),
+                _ => None,
+            },
+            "JOIN" => arg(0).map(Command::JOIN),
+            "PART" => arg(0).map(Command::PART),
+            "KICK" => match (arg(0), arg(1)) {
+                (Some(channel), Some(user)) => Some(Command::KICK(channel, user, arg(2))),
+                _ => None,
+            },
+            "NICK" => arg(0).map(Command::NICK),
+            "QUIT" => Some(Command::QUIT(arg(0).unwrap_or_default())),
+            "TOPIC" => arg(0).map(|channel| Command::TOPIC(channel, arg(1))),
+            "INVITE" => match (arg(0), arg(1)) {
+                (Some(user), Some(channel)) => Some(Command::INVITE(user, channel)),
+                _ => None,
+            },
+            "MODE" => arg(0).map(|target| {
+                let mode = (args.len() > 1).then(|| args[1..].join(" "));
+                Command::MODE(target, mode)
+            }),
+            "ERROR" => Some(Command::ERROR(arg(0).unwrap_or_default())),
+            // CAP <client> <subcommand> :<capabilities>
+            "CAP" => match args.get(1).map(|sub| sub.to_ascii_uppercase()).as_deref() {
+                Some("ACK") => Some(Command::CAP(CapMode::ACK(list(2)))),
+                Some("NAK") => Some(Command::CAP(CapMode::NAK(list(2)))),
+                Some("REQ") => Some(Command::CAP(CapMode::REQ(list(2)))),
+                _ => None,
+            },
+            _ => None,
+        };
+        command.unwrap_or_else(other)
+    }
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.tags.is_empty() {
+            let mut tags: Vec<_> = self.tags.iter().collect();
+            tags.sort();
+            write!(f, "@")?;
+            for (i, (key, value)) in tags.into_iter().enumerate() {
+                if i > 0 {
+                    write!(f, ";")?;
+                }
+                write!(f, "{key}")?;
+                if !value.is_empty() {
+                    write!(f, "={}", escape_tag_value(value))?;
+                }
+            }
+            write!(f, " ")?;
+        }
+        if let Some(prefix) = &self.prefix {
+            write!(f, ":{prefix} ")?;
+        }
+        write!(f, "{}", self.command)?;
+        for param in &self.params {
+            write!(f, " {param}")?;
+        }
+        if let Some(trailing) = &self.trailing {
+            write!(f, " :{trailing}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use crate::commands::Command;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// At most `messages` every `per`, short bursts up to `messages` are fine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    pub messages: u32,
+    pub per: Duration,
+}
+
+/// Limits of the [`SendQueue`], the defaults are Twitch's for a bot that isn't a moderator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimits {
+    /// PRIVMSGs and NOTICEs to all channels together.
+    pub global: Limit,
+    /// PRIVMSGs and NOTICEs to a single channel.
+    pub channel: Limit,
+    pub join: Limit,
+    /// The same message to the same channel within this window is refused, Twitch drops them
+    /// anyway. Zero allows duplicates.
+    pub duplicate_window: Duration,
+    /// Longest the queue gets before [`SendQueue::push`] refuses more.
+    pub max_queued: usize,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            global: Limit {
+                messages: 20,
+                per: Duration::from_secs(30),
+            },
+            channel: Limit {
+                messages: 1,
+                per: Duration::from_secs(1),
+            },
+            join: Limit {
+                messages: 20,
+                per: Duration::from_secs(10),
+            },
+            duplicate_window: Duration::from_secs(30),
+            max_queued: 100,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Twitch's limits for a bot that is a moderator or the broadcaster of the channels it talks
+    /// in, duplicates are allowed too.
+    #[must_use]
+    pub fn twitch_moderator() -> Self {
+        let global = Limit {
+            messages: 100,
+            per: Duration::from_secs(30),
+        };
+        Self {
+            global,
+            channel: global,
+            duplicate_window: Duration::ZERO,
+            ..Self::default()
+        }
+    }
+}
+
+/// Why a command was not queued.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueError {
+    /// [`RateLimits::max_queued`] commands are already waiting.
+    Full,
+    /// The same message went to the same channel within [`RateLimits::duplicate_window`].
+    Duplicate,
+}
+
+impl std::fmt::Display for QueueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueError::Full => write!(f, "Send queue is full"),
+            QueueError::Duplicate => write!(f, "Same message was sent just now"),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    limit: Limit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: Limit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.messages),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let rate = f64::from(self.limit.messages) / self.limit.per.as_secs_f64().max(f64::EPSILON);
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.limit.messages));
+        self.updated = now;
+    }
+
+    fn ready(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= 1.0
+    }
+
+    /// Time until the next token, zero when there is one.
+    fn ready_in(&self, now: Instant) -> Duration {
+        let mut bucket = self.clone();
+        bucket.refill(now);
+        let missing = 1.0 - bucket.tokens;
+        if missing <= 0.0 || self.limit.messages == 0 {
+            return Duration::ZERO;
+        }
+        self.limit
+            .per
+            .mul_f64(missing / f64::from(self.limit.messages))
+    }
+}
+
+/// Which bucket a command is paid from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Bucket {
+    Channel(String),
+    Join,
+    /// Anything else, e.g. PART or MODE, is sent right away.
+    Unlimited,
+}
+
+fn bucket(command: &Command) -> Bucket {
+    match command {
+        Command::PRIVMSG(_, target, _) | Command::NOTICE(target, _) => {
+            Bucket::Channel(target.to_lowercase())
+        }
+        Command::JOIN(_) => Bucket::Join,
+        _ => Bucket::Unlimited,
+    }
+}
+
+fn duplicate_key(command: &Command) -> Option<(String, String)> {
+    match command {
+        Command::PRIVMSG(_, target, text) | Command::NOTICE(target, text) => {
+            Some((target.to_lowercase(), text.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Outgoing commands waiting for the rate limits, see [`crate::Client::enqueue`]. Commands to
+/// one channel keep their order, a busy channel doesn't hold up the others.
+#[derive(Debug)]
+pub struct SendQueue {
+    limits: RateLimits,
+    queue: VecDeque<Command>,
+    global: TokenBucket,
+    join: TokenBucket,
+    channels: HashMap<String, TokenBucket>,
+    /// When each message was last sent, for dropping duplicates.
+    sent: HashMap<(String, String), Instant>,
+}
+
+impl SendQueue {
+    #[must_use]
+    pub fn new(limits: RateLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            global: TokenBucket::new(limits.global, now),
+            join: TokenBucket::new(limits.join, now),
+            limits,
+            queue: VecDeque::new(),
+            channels: HashMap::new(),
+            sent: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queue a command, it never waits.
+    /// # Errors
+    /// Returns error if the queue is full or the message is a duplicate.
+    pub fn push(&mut self, command: Command, now: Instant) -> Result<(), QueueError> {
+        if self.queue.len() >= self.limits.max_queued {
+            return Err(QueueError::Full);
+        }
+        if let Some(key) = duplicate_key(&command) {
+            let window = self.limits.duplicate_window;
+            self.sent
+                .retain(|_, sent| now.saturating_duration_since(*sent) < window);
+            let queued = || {
+                self.queue
+                    .iter()
+                    .any(|queued| duplicate_key(queued).as_ref() == Some(&key))
+            };
+            if !window.is_zero() && (self.sent.contains_key(&key) || queued()) {
+                return Err(QueueError::Duplicate);
+            }
+        }
+        self.queue.push_back(command);
+        Ok(())
+    }
+
+    /// The first command the limits allow now, `None` if everything has to wait.
+    pub fn pop_ready(&mut self, now: Instant) -> Option<Command> {
+        let global_ready = self.global.ready(now);
+        let mut blocked = Vec::new();
+        let index = self.queue.iter().position(|command| {
+            let bucket = bucket(command);
+            if blocked.contains(&bucket) {
+                return false;
+            }
+            let ready = match &bucket {
+                Bucket::Unlimited => true,
+                Bucket::Join => self.join.ready(now),
+                Bucket::Channel(channel) => {
+                    global_ready
+                        && self
+                            .channels
+                            .entry(channel.clone())
+                            .or_insert_with(|| TokenBucket::new(self.limits.channel, now))
+                            .ready(now)
+                }
+            };
+            if !ready {
+                blocked.push(bucket);
+            }
+            ready
+        })?;
+
+        let command = self.queue.remove(index)?;
+        match bucket(&command) {
+            Bucket::Unlimited => {}
+            Bucket::Join => self.join.tokens -= 1.0,
+            Bucket::Channel(channel) => {
+                self.global.tokens -= 1.0;
+                if let Some(bucket) = self.channels.get_mut(&channel) {
+                    bucket.tokens -= 1.0;
+                }
+            }
+        }
+        if let Some(key) = duplicate_key(&command) {
+            self.sent.insert(key, now);
+        }
+        Some(command)
+    }
+
+    /// How long until [`SendQueue::pop_ready`] has something, `None` when the queue is empty.
+    #[must_use]
+    pub fn ready_in(&self, now: Instant) -> Option<Duration> {
+        self.queue
+            .iter()
+            .map(|command| match bucket(command) {
+                Bucket::Unlimited => Duration::ZERO,
+                Bucket::Join => self.join.ready_in(now),
+                Bucket::Channel(channel) => {
+                    let channel = self
+                        .channels
+                        .get(&channel)
+                        .map_or(Duration::ZERO, |bucket| bucket.ready_in(now));
+                    channel.max(self.global.ready_in(now))
+                }
+            })
+            .min()
+    }
+}
@@ -0,0 +1,99 @@
+/// Longest line without tags, including the `\r\n`.
+pub const MAX_LINE_LENGTH: usize = 512;
+/// Longest tag section, including the leading `@` and the space after it.
+pub const MAX_TAGS_LENGTH: usize = 8191;
+
+/// A line that broke the length limits, it is dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineError {
+    /// The part after the tags is longer than [`MAX_LINE_LENGTH`].
+    LineTooLong(usize),
+    /// The tags are longer than [`MAX_TAGS_LENGTH`].
+    TagsTooLong(usize),
+}
+
+impl std::fmt::Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineError::LineTooLong(length) => write!(
+                f,
+                "Dropping IRC line of {length} bytes, the limit is {MAX_LINE_LENGTH}"
+            ),
+            LineError::TagsTooLong(length) => write!(
+                f,
+                "Dropping IRC line with {length} bytes of tags, the limit is {MAX_TAGS_LENGTH}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// Splits the bytes coming from the server into lines. Reads can end anywhere, in the middle of
+/// a line or of a UTF-8 character, so bytes are kept until their line is complete.
+/// ```
+/// # use circe::reader::LineBuffer;
+/// let mut lines = LineBuffer::default();
+/// lines.push(b"PING :tmi.twitch.tv\r\n:nick!user@host PRIV");
+/// assert_eq!(lines.next_line(), Some(Ok("PING :tmi.twitch.tv".to_string())));
+/// assert_eq!(lines.next_line(), None);
+/// lines.push(b"MSG #main :hi\r\n");
+/// assert_eq!(lines.next_line(), Some(Ok(":nick!user@host PRIVMSG #main :hi".to_string())));
+/// ```
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    buffer: Vec<u8>,
+    /// Dropping the rest of a line that was already too long without its end.
+    discarding: bool,
+}
+
+impl LineBuffer {
+    /// Add bytes read from the stream.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The next complete line without its `\r\n`, `None` until more bytes are pushed. Empty
+    /// lines are skipped and invalid UTF-8 is replaced.
+    pub fn next_line(&mut self) -> Option<Result<String, LineError>> {
+        loop {
+            let Some(end) = self.buffer.iter().position(|&b| b == b'\n') else {
+                // no end in sight and already too long, stop keeping it around
+                if self.buffer.len() > MAX_TAGS_LENGTH + MAX_LINE_LENGTH {
+                    let length = self.buffer.len();
+                    self.buffer.clear();
+                    if !std::mem::replace(&mut self.discarding, true) {
+                        return Some(Err(LineError::LineTooLong(length)));
+                    }
+                }
+                return None;
+            };
+
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            if std::mem::take(&mut self.discarding) {
+                continue;
+            }
+            let line = line[..end].strip_suffix(b"\r").unwrap_or(&line[..end]);
+            if line.is_empty() {
+                continue;
+            }
+
+            let tags = match line.first() {
+                Some(b'@') => line
+                    .iter()
+                    .position(|&b| b == b' ')
+                    .map_or(line.len(), |i| i + 1),
+                _ => 0,
+            };
+            if tags > MAX_TAGS_LENGTH {
+                return Some(Err(LineError::TagsTooLong(tags)));
+            }
+            let length = line.len() - tags + 2;
+            if length > MAX_LINE_LENGTH {
+                return Some(Err(LineError::LineTooLong(length)));
+            }
+
+            return Some(Ok(String::from_utf8_lossy(line).into_owned()));
+        }
+    }
+}
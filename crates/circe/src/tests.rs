@@ -0,0 +1,647 @@
+use crate::commands::{CapMode, Command};
+use crate::connection::{is_lost, Backoff};
+use crate::message::{Message, ParseError, Prefix};
+use crate::ratelimit::{Limit, QueueError, RateLimits, SendQueue};
+use crate::reader::{LineBuffer, LineError, MAX_LINE_LENGTH, MAX_TAGS_LENGTH};
+use crate::{Client, Config};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|item| (*item).to_string()).collect()
+}
+
+#[test]
+fn test_parse_prefix() {
+    assert_eq!(
+        Prefix::parse("ronni!ronni@ronni.tmi.twitch.tv"),
+        Prefix {
+            nick: "ronni".into(),
+            user: Some("ronni".into()),
+            host: Some("ronni.tmi.twitch.tv".into()),
+        }
+    );
+    assert_eq!(
+        Prefix::parse("tmi.twitch.tv"),
+        Prefix {
+            nick: "tmi.twitch.tv".into(),
+            user: None,
+            host: None,
+        }
+    );
+    assert_eq!(
+        Prefix::parse("NickServ@services.libera.chat"),
+        Prefix {
+            nick: "NickServ".into(),
+            user: None,
+            host: Some("services.libera.chat".into()),
+        }
+    );
+}
+
+#[test]
+fn test_parse_message() {
+    let message = Message::parse(
+        "@badge-info=;badges=broadcaster/1;color=#0000FF;display-name=Ronni;mod=0 \
+         :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni :Kappa Keepo Kappa\r\n",
+    )
+    .unwrap();
+    assert_eq!(message.tags["badges"], "broadcaster/1");
+    assert_eq!(message.tags["badge-info"], "");
+    assert_eq!(message.tags["display-name"], "Ronni");
+    assert_eq!(message.nick(), "ronni");
+    assert_eq!(message.command, "PRIVMSG");
+    assert_eq!(message.params, ["#ronni"]);
+    assert_eq!(message.trailing.as_deref(), Some("Kappa Keepo Kappa"));
+
+    // trailing without a colon, an empty trailing and a lower case command
+    let message = Message::parse("privmsg #main hello").unwrap();
+    assert_eq!(message.command, "PRIVMSG");
+    assert_eq!(message.args(), ["#main", "hello"]);
+    let message = Message::parse("TOPIC #main :").unwrap();
+    assert_eq!(message.trailing.as_deref(), Some(""));
+
+    // more than 14 middle parameters, the rest is trailing
+    let message = Message::parse("CMD 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16").unwrap();
+    assert_eq!(message.params.len(), 14);
+    assert_eq!(message.trailing.as_deref(), Some("15 16"));
+}
+
+#[test]
+fn test_display_roundtrip() {
+    for line in [
+        "@emote-only=0;room-id=12345678 :tmi.twitch.tv ROOMSTATE #bar",
+        ":calcium.libera.chat 005 circe CHANTYPES=# EXCEPTS INVEX :are supported by this server",
+        "PING :tmi.twitch.tv",
+        ":nick!user@host PRIVMSG #main :hello :) there",
+    ] {
+        assert_eq!(Message::parse(line).unwrap().to_string(), line);
+    }
+}
+
+#[test]
+fn test_malformed_lines() {
+    assert_eq!(Message::parse(""), Err(ParseError::Empty));
+    assert_eq!(Message::parse(" \r\n"), Err(ParseError::Empty));
+    assert_eq!(Message::parse("@a=b"), Err(ParseError::MissingCommand));
+    assert_eq!(
+        Message::parse(":prefix.only"),
+        Err(ParseError::MissingCommand)
+    );
+    assert_eq!(
+        Message::parse("@a=b :nick!user@host  "),
+        Err(ParseError::MissingCommand)
+    );
+
+    // none of these may panic, odd ones end up as OTHER
+    for line in [
+        ":",
+        "@",
+        "@;;; :",
+        "PRIVMSG",
+        "PRIVMSG #main",
+        ":nick PRIVMSG",
+        ":tmi.twitch.tv PRIVMSG #main :no user in prefix",
+        "KICK #main",
+        "CAP * ACK",
+        "PING",
+        "999999",
+        "\u{1f980} \u{1f980} :\u{1f980}",
+    ] {
+        let _ = Command::command_from_str(line);
+    }
+    assert_eq!(
+        Command::command_from_str("PRIVMSG #main"),
+        Command::OTHER("PRIVMSG #main".into())
+    );
+    assert_eq!(Command::command_from_str("  "), Command::OTHER("".into()));
+}
+
+#[test]
+fn test_twitch_corpus() {
+    let corpus = [
+        (
+            ":tmi.twitch.tv 001 circebot :Welcome, GLHF!",
+            Command::NUMERIC(1, strings(&["circebot", "Welcome, GLHF!"])),
+        ),
+        (
+            ":tmi.twitch.tv 372 circebot :You are in a maze of twisty passages, all alike.",
+            Command::NUMERIC(
+                372,
+                strings(&[
+                    "circebot",
+                    "You are in a maze of twisty passages, all alike.",
+                ]),
+            ),
+        ),
+        (
+            ":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands",
+            Command::CAP(CapMode::ACK(strings(&[
+                "twitch.tv/tags",
+                "twitch.tv/commands",
+            ]))),
+        ),
+        (
+            ":tmi.twitch.tv CAP * NAK :twitch.tv/unknown",
+            Command::CAP(CapMode::NAK(strings(&["twitch.tv/unknown"]))),
+        ),
+        (
+            ":circebot!circebot@circebot.tmi.twitch.tv JOIN #ronni",
+            Command::JOIN("#ronni".into()),
+        ),
+        (
+            ":circebot.tmi.twitch.tv 353 circebot = #ronni :circebot",
+            Command::NUMERIC(353, strings(&["circebot", "=", "#ronni", "circebot"])),
+        ),
+        (
+            "@badge-info=subscriber/8;badges=subscriber/6;bits=100;color=#0D4200;\
+             display-name=ronni;emotes=;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;mod=0;\
+             room-id=1337;subscriber=1;tmi-sent-ts=1507246572675;turbo=1;user-id=1337;\
+             user-type=global_mod :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni \
+             :cheer100 draw a cat",
+            Command::PRIVMSG(
+                "ronni".into(),
+                "#ronni".into(),
+                "cheer100 draw a cat".into(),
+            ),
+        ),
+        (
+            "@msg-id=slow_off :tmi.twitch.tv NOTICE #dallas :This room is no longer in slow mode.",
+            Command::NOTICE(
+                "#dallas".into(),
+                "This room is no longer in slow mode.".into(),
+            ),
+        ),
+        (
+            ":ronni!ronni@ronni.tmi.twitch.tv PART #ronni",
+            Command::PART("#ronni".into()),
+        ),
+        ("PING :tmi.twitch.tv", Command::PING("tmi.twitch.tv".into())),
+        (
+            ":tmi.twitch.tv PONG tmi.twitch.tv :circe",
+            Command::PONG("circe".into()),
+        ),
+        (
+            ":tmi.twitch.tv NOTICE * :Login authentication failed",
+            Command::NOTICE("*".into(), "Login authentication failed".into()),
+        ),
+    ];
+    for (line, command) in corpus {
+        assert_eq!(Command::command_from_str(line), command, "{line}");
+    }
+
+    // Twitch only commands come through as OTHER with the whole line
+    let line = "@emote-only=0;followers-only=-1;r9k=0;room-id=12345678;slow=0;subs-only=0 \
+                :tmi.twitch.tv ROOMSTATE #bar";
+    assert_eq!(
+        Command::command_from_str(line),
+        Command::OTHER(Message::parse(line).unwrap().to_string())
+    );
+    assert!(matches!(
+        Command::command_from_str(":tmi.twitch.tv RECONNECT"),
+        Command::OTHER(line) if line.contains("RECONNECT")
+    ));
+}
+
+#[test]
+fn test_libera_corpus() {
+    let corpus = [
+        (
+            ":calcium.libera.chat NOTICE * :*** Checking Ident",
+            Command::NOTICE("*".into(), "*** Checking Ident".into()),
+        ),
+        (
+            ":calcium.libera.chat CAP * LS :account-notify away-notify sasl",
+            Command::OTHER(":calcium.libera.chat CAP * LS :account-notify away-notify sasl".into()),
+        ),
+        (
+            ":calcium.libera.chat 001 circe :Welcome to the Libera.Chat Internet Relay Chat \
+             Network circe",
+            Command::NUMERIC(
+                1,
+                strings(&[
+                    "circe",
+                    "Welcome to the Libera.Chat Internet Relay Chat Network circe",
+                ]),
+            ),
+        ),
+        (
+            ":calcium.libera.chat 433 * circe :Nickname is already in use.",
+            Command::NUMERIC(433, strings(&["*", "circe", "Nickname is already in use."])),
+        ),
+        (
+            ":circe MODE circe :+Ziw",
+            Command::MODE("circe".into(), Some("+Ziw".into())),
+        ),
+        (
+            ":ChanServ!ChanServ@services.libera.chat MODE #circe +o circe",
+            Command::MODE("#circe".into(), Some("+o circe".into())),
+        ),
+        (
+            ":circe!~circe@user/circe JOIN #circe",
+            Command::JOIN("#circe".into()),
+        ),
+        (
+            ":calcium.libera.chat 332 circe #circe :Welcome to #circe",
+            Command::NUMERIC(332, strings(&["circe", "#circe", "Welcome to #circe"])),
+        ),
+        (
+            ":op!~op@user/op KICK #circe spammer :no spam",
+            Command::KICK("#circe".into(), "spammer".into(), Some("no spam".into())),
+        ),
+        (
+            ":op!~op@user/op KICK #circe spammer",
+            Command::KICK("#circe".into(), "spammer".into(), None),
+        ),
+        (
+            ":op!~op@user/op TOPIC #circe :New topic",
+            Command::TOPIC("#circe".into(), Some("New topic".into())),
+        ),
+        (
+            ":op!~op@user/op INVITE circe :#secret",
+            Command::INVITE("circe".into(), "#secret".into()),
+        ),
+        (":old!~old@user/old NICK :new", Command::NICK("new".into())),
+        (
+            ":someone!~someone@1.2.3.4 QUIT :Quit: Leaving",
+            Command::QUIT("Quit: Leaving".into()),
+        ),
+        (":someone!~someone@1.2.3.4 QUIT", Command::QUIT("".into())),
+        (
+            ":someone!~someone@1.2.3.4 PRIVMSG circe :\u{1}ACTION waves\u{1}",
+            Command::PRIVMSG(
+                "someone".into(),
+                "circe".into(),
+                "\u{1}ACTION waves\u{1}".into(),
+            ),
+        ),
+        (
+            "ERROR :Closing Link: 1.2.3.4 (Quit: circe)",
+            Command::ERROR("Closing Link: 1.2.3.4 (Quit: circe)".into()),
+        ),
+    ];
+    for (line, command) in corpus {
+        assert_eq!(Command::command_from_str(line), command, "{line}");
+    }
+}
+
+#[test]
+fn test_tag_escaping() {
+    let message = Message::parse(
+        "@system-msg=ronni\\shas\\ssubscribed\\:\\sgreat!;a=back\\\\slash;b=end\\;c \
+         :tmi.twitch.tv USERNOTICE #ronni",
+    )
+    .unwrap();
+    assert_eq!(
+        message.tag("system-msg"),
+        Some("ronni has subscribed; great!")
+    );
+    assert_eq!(message.tag("a"), Some("back\\slash"));
+    assert_eq!(message.tag("b"), Some("end"));
+    assert_eq!(message.tag("c"), Some(""));
+    assert_eq!(message.tag("d"), None);
+
+    let line = "@a=back\\\\slash;system-msg=ronni\\shas\\ssubscribed\\:\\sgreat! \
+                :tmi.twitch.tv USERNOTICE #ronni";
+    assert_eq!(Message::parse(line).unwrap().to_string(), line);
+}
+
+#[test]
+fn test_twitch_tags() {
+    let message = Message::parse(
+        "@badge-info=subscriber/8;badges=moderator/1,subscriber/6,bits/1000;bits=100;\
+         color=#0D4200;display-name=Ronni;mod=1;subscriber=1;user-id=1337 \
+         :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni :cheer100 draw a cat",
+    )
+    .unwrap();
+    assert_eq!(
+        message.badges(),
+        [("moderator", "1"), ("subscriber", "6"), ("bits", "1000")]
+    );
+    assert_eq!(message.display_name(), "Ronni");
+    assert!(message.is_moderator());
+    assert!(message.is_subscriber());
+    assert!(!message.is_broadcaster());
+    assert_eq!(message.bits(), 100);
+
+    let message = Message::parse(
+        "@badges=broadcaster/1,founder/0;display-name=;mod=0;subscriber=0 \
+         :owner!owner@owner.tmi.twitch.tv PRIVMSG #owner :hi",
+    )
+    .unwrap();
+    assert!(message.is_broadcaster());
+    assert!(message.is_moderator());
+    assert!(message.is_subscriber());
+    assert_eq!(message.display_name(), "owner");
+    assert_eq!(message.bits(), 0);
+
+    // plain IRC without tags
+    let message = Message::parse(":someone!~someone@1.2.3.4 PRIVMSG #circe :hi").unwrap();
+    assert!(message.badges().is_empty());
+    assert!(!message.is_moderator() && !message.is_subscriber());
+    assert_eq!(message.display_name(), "someone");
+
+    assert_eq!(
+        Command::command_from_str("CAP * REQ :twitch.tv/membership"),
+        Command::CAP(CapMode::REQ(strings(&["twitch.tv/membership"])))
+    );
+}
+
+#[test]
+fn test_line_buffer() {
+    let mut lines = LineBuffer::default();
+    assert_eq!(lines.next_line(), None);
+
+    // several lines in one read, the last one cut off
+    lines.push(b":tmi.twitch.tv 001 circebot :Welcome, GLHF!\r\n:tmi.twitch.tv 002 circebot :Your host is tmi.twitch.tv\r\n:tmi.twi");
+    assert_eq!(
+        lines.next_line(),
+        Some(Ok(":tmi.twitch.tv 001 circebot :Welcome, GLHF!".into()))
+    );
+    assert_eq!(
+        lines.next_line(),
+        Some(Ok(
+            ":tmi.twitch.tv 002 circebot :Your host is tmi.twitch.tv".into()
+        ))
+    );
+    assert_eq!(lines.next_line(), None);
+    lines.push(b"tch.tv 003 circebot :This server is rather new\r");
+    assert_eq!(lines.next_line(), None);
+    lines.push(b"\n\r\n\nPING :tmi.twitch.tv\n");
+    assert_eq!(
+        lines.next_line(),
+        Some(Ok(
+            ":tmi.twitch.tv 003 circebot :This server is rather new".into()
+        ))
+    );
+    // empty lines are skipped and a bare \n ends a line too
+    assert_eq!(lines.next_line(), Some(Ok("PING :tmi.twitch.tv".into())));
+    assert_eq!(lines.next_line(), None);
+}
+
+#[test]
+fn test_line_buffer_utf8() {
+    let line = ":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni :draw a 🦀 at 10,10 ñ\r\n";
+    let mut lines = LineBuffer::default();
+    // every possible split, including inside the crab
+    for split in 0..line.len() {
+        lines.push(&line.as_bytes()[..split]);
+        lines.push(&line.as_bytes()[split..]);
+        assert_eq!(lines.next_line(), Some(Ok(line.trim_end().to_string())));
+        assert_eq!(lines.next_line(), None);
+    }
+    // byte by byte
+    for byte in line.bytes() {
+        lines.push(&[byte]);
+    }
+    assert_eq!(lines.next_line(), Some(Ok(line.trim_end().to_string())));
+
+    lines.push(b"PRIVMSG #main :\xff\xfe broken\r\n");
+    assert_eq!(
+        lines.next_line(),
+        Some(Ok("PRIVMSG #main :\u{fffd}\u{fffd} broken".into()))
+    );
+}
+
+#[test]
+fn test_line_buffer_limits() {
+    let mut lines = LineBuffer::default();
+
+    // exactly at the limit, 510 bytes and \r\n
+    let longest = format!("PRIVMSG #main :{}", "a".repeat(MAX_LINE_LENGTH - 17));
+    lines.push(format!("{longest}\r\n").as_bytes());
+    assert_eq!(lines.next_line(), Some(Ok(longest.clone())));
+
+    lines.push(format!("{longest}a\r\nPING :after\r\n").as_bytes());
+    assert_eq!(
+        lines.next_line(),
+        Some(Err(LineError::LineTooLong(MAX_LINE_LENGTH + 1)))
+    );
+    assert_eq!(lines.next_line(), Some(Ok("PING :after".into())));
+
+    // tags have their own limit on top of the line
+    let tags = format!("@a={} ", "b".repeat(MAX_TAGS_LENGTH - 4));
+    lines.push(format!("{tags}{longest}\r\n").as_bytes());
+    assert_eq!(lines.next_line(), Some(Ok(format!("{tags}{longest}"))));
+    lines.push(format!("@a={} PING :x\r\n", "b".repeat(MAX_TAGS_LENGTH)).as_bytes());
+    assert_eq!(
+        lines.next_line(),
+        Some(Err(LineError::TagsTooLong(MAX_TAGS_LENGTH + 4)))
+    );
+
+    // a line without an end is dropped once it can't fit anymore, then everything up to its end
+    let endless = "a".repeat(MAX_TAGS_LENGTH + MAX_LINE_LENGTH + 1);
+    lines.push(endless.as_bytes());
+    assert_eq!(
+        lines.next_line(),
+        Some(Err(LineError::LineTooLong(endless.len())))
+    );
+    lines.push(endless.as_bytes());
+    assert_eq!(lines.next_line(), None);
+    lines.push(b"still the same line\r\nPING :done\r\n");
+    assert_eq!(lines.next_line(), Some(Ok("PING :done".into())));
+    assert_eq!(lines.next_line(), None);
+}
+
+#[test]
+fn test_reconnect_backoff() {
+    let backoff = Backoff::default();
+    assert_eq!(backoff.delay(1), Duration::from_secs(1));
+    assert_eq!(backoff.delay(2), Duration::from_secs(2));
+    assert_eq!(backoff.delay(5), Duration::from_secs(16));
+    assert_eq!(backoff.delay(7), Duration::from_secs(60));
+    assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(60));
+    // attempt 0 is treated like the first one
+    assert_eq!(backoff.delay(0), Duration::from_secs(1));
+
+    // RECONNECT is handled by the client, the command itself is nothing special
+    assert_eq!(
+        Command::command_from_str(":tmi.twitch.tv RECONNECT"),
+        Command::OTHER(":tmi.twitch.tv RECONNECT".into())
+    );
+
+    // a read timing out leaves the connection alone
+    let error = |kind: std::io::ErrorKind| std::io::Error::from(kind);
+    assert!(is_lost(&error(std::io::ErrorKind::UnexpectedEof)));
+    assert!(is_lost(&error(std::io::ErrorKind::ConnectionReset)));
+    assert!(is_lost(&error(std::io::ErrorKind::InvalidData)));
+    assert!(!is_lost(&error(std::io::ErrorKind::TimedOut)));
+    assert!(!is_lost(&error(std::io::ErrorKind::WouldBlock)));
+}
+
+fn privmsg(channel: &str, text: &str) -> Command {
+    Command::PRIVMSG(String::new(), channel.into(), text.into())
+}
+
+#[test]
+fn test_send_queue_limits() {
+    let limits = RateLimits {
+        global: Limit {
+            messages: 3,
+            per: Duration::from_secs(30),
+        },
+        channel: Limit {
+            messages: 1,
+            per: Duration::from_secs(1),
+        },
+        duplicate_window: Duration::ZERO,
+        ..RateLimits::default()
+    };
+    let start = Instant::now();
+    let mut queue = SendQueue::new(limits);
+    assert_eq!(queue.ready_in(start), None);
+
+    for text in ["a1", "a2"] {
+        queue.push(privmsg("#a", text), start).unwrap();
+    }
+    queue.push(privmsg("#B", "b1"), start).unwrap();
+    queue.push(Command::PART("#c".into()), start).unwrap();
+
+    // #a's second message waits for its channel, #b and the PART don't wait behind it
+    assert_eq!(queue.pop_ready(start), Some(privmsg("#a", "a1")));
+    assert_eq!(queue.pop_ready(start), Some(privmsg("#B", "b1")));
+    assert_eq!(queue.pop_ready(start), Some(Command::PART("#c".into())));
+    assert_eq!(queue.pop_ready(start), None);
+    assert_eq!(queue.ready_in(start), Some(Duration::from_secs(1)));
+
+    let later = start + Duration::from_secs(1);
+    assert_eq!(queue.pop_ready(later), Some(privmsg("#a", "a2")));
+    assert!(queue.is_empty());
+
+    // three messages used up the global limit, it refills by one every 10 seconds and got a
+    // tenth back since the start
+    queue.push(privmsg("#b", "b2"), later).unwrap();
+    let later = later + Duration::from_secs(5);
+    assert_eq!(queue.pop_ready(later), None);
+    assert_eq!(queue.ready_in(later), Some(Duration::from_secs(4)));
+    let later = later + Duration::from_secs(4);
+    assert_eq!(queue.pop_ready(later), Some(privmsg("#b", "b2")));
+
+    // JOINs have their own limit
+    let mut queue = SendQueue::new(RateLimits::default());
+    for i in 0..21 {
+        queue.push(Command::JOIN(format!("#{i}")), start).unwrap();
+    }
+    for i in 0..20 {
+        assert_eq!(queue.pop_ready(start), Some(Command::JOIN(format!("#{i}"))));
+    }
+    assert_eq!(queue.pop_ready(start), None);
+    assert_eq!(
+        queue.pop_ready(start + Duration::from_millis(500)),
+        Some(Command::JOIN("#20".into()))
+    );
+}
+
+#[test]
+fn test_send_queue_duplicates() {
+    let start = Instant::now();
+    let mut queue = SendQueue::new(RateLimits {
+        max_queued: 3,
+        ..RateLimits::default()
+    });
+
+    queue.push(privmsg("#main", "hi"), start).unwrap();
+    // already queued, channel names are case insensitive
+    assert_eq!(
+        queue.push(privmsg("#Main", "hi"), start),
+        Err(QueueError::Duplicate)
+    );
+    assert_eq!(queue.pop_ready(start), Some(privmsg("#main", "hi")));
+    // just sent
+    let later = start + Duration::from_secs(29);
+    assert_eq!(
+        queue.push(privmsg("#main", "hi"), later),
+        Err(QueueError::Duplicate)
+    );
+    // other channels, other texts and NOTICEs with another text are fine
+    queue.push(privmsg("#other", "hi"), later).unwrap();
+    queue.push(privmsg("#main", "hi again"), later).unwrap();
+    queue
+        .push(Command::NOTICE("#main".into(), "hey".into()), later)
+        .unwrap();
+    assert_eq!(
+        queue.push(privmsg("#third", "full"), later),
+        Err(QueueError::Full)
+    );
+    while queue.pop_ready(later + Duration::from_secs(2)).is_some() {}
+
+    // the window is over
+    let later = start + Duration::from_secs(30);
+    queue.push(privmsg("#main", "hi"), later).unwrap();
+
+    // moderators may repeat themselves
+    let mut queue = SendQueue::new(RateLimits::twitch_moderator());
+    queue.push(privmsg("#main", "hi"), start).unwrap();
+    queue.push(privmsg("#main", "hi"), start).unwrap();
+    assert_eq!(queue.pop_ready(start), Some(privmsg("#main", "hi")));
+    assert_eq!(queue.pop_ready(start), Some(privmsg("#main", "hi")));
+}
+
+#[test]
+fn test_queue_flushes_while_idle() -> std::io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let mut client = Client::over_tcp(
+        Config {
+            rate_limits: RateLimits {
+                channel: Limit {
+                    messages: 1,
+                    per: Duration::from_millis(100),
+                },
+                ..RateLimits::default()
+            },
+            ..Config::default()
+        },
+        TcpStream::connect(listener.local_addr()?)?,
+    );
+    let (mut server, _) = listener.accept()?;
+    server.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    client.enqueue(privmsg("#main", ":one")).unwrap();
+    client.enqueue(privmsg("#main", ":two")).unwrap();
+    let reader = std::thread::spawn(move || client.read_message().map(|m| m.command));
+
+    // the second one waits for the channel limit, not for the server to send something
+    let mut lines = BufReader::new(server.try_clone()?).lines();
+    assert_eq!(
+        lines.next().transpose()?.as_deref(),
+        Some("PRIVMSG #main :one")
+    );
+    assert_eq!(
+        lines.next().transpose()?.as_deref(),
+        Some("PRIVMSG #main :two")
+    );
+
+    server.write_all(b":tmi.twitch.tv NOTICE * :done\r\n")?;
+    assert_eq!(reader.join().unwrap().unwrap(), "NOTICE");
+    Ok(())
+}
+
+#[test]
+fn test_read_timeout() -> std::io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let mut client = Client::over_tcp(
+        Config {
+            read_timeout: Some(Duration::from_millis(50)),
+            ..Config::default()
+        },
+        TcpStream::connect(listener.local_addr()?)?,
+    );
+    let (mut server, _) = listener.accept()?;
+
+    // the caller gets control back, the connection stays
+    let started = Instant::now();
+    assert!(client.read_message().is_err());
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    client.enqueue(privmsg("#main", ":still here")).unwrap();
+    server.write_all(b"PING :tmi.twitch.tv\r\n")?;
+    assert_eq!(client.read_message().unwrap().command, "PING");
+
+    server.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut lines = BufReader::new(server).lines();
+    assert_eq!(
+        lines.next().transpose()?.as_deref(),
+        Some("PRIVMSG #main :still here")
+    );
+    Ok(())
+}
@@ -1,13 +1,27 @@
 #![allow(dead_code)]
 
-use color_eyre::Result;
+use crate::{framer::PacketFramer, models::PrinterModel, NiimbotError, Result};
 use rusb::{DeviceHandle, GlobalContext};
 use serialport::SerialPort;
-use std::time::Duration;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
 
 pub trait NiimbotPrinterAdapter {
     fn send(&mut self, bytes: &[u8]) -> Result<usize>;
     fn recv(&mut self, bytes: &mut [u8]) -> Result<usize>;
+
+    /// The model the adapter already knows it is talking to, e.g. from the USB product id.
+    fn model(&self) -> Option<PrinterModel> {
+        None
+    }
+
+    /// How long a read or write may block, adapters without a timeout of their own ignore it.
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub struct SerialPortAdapter {
@@ -32,28 +46,116 @@ impl NiimbotPrinterAdapter for SerialPortAdapter {
     fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
         Ok(self.serial_port.read(bytes)?)
     }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        Ok(self.serial_port.set_timeout(timeout)?)
+    }
 }
 
 pub struct UsbAdapter {
     pub device_handle: DeviceHandle<GlobalContext>,
+    pub model: Option<PrinterModel>,
+    /// Bulk transfer timeout.
+    pub timeout: Duration,
 }
 
 impl UsbAdapter {
     pub fn new(device_handle: DeviceHandle<GlobalContext>) -> Result<Self, rusb::Error> {
-        Ok(Self { device_handle })
+        Ok(Self {
+            device_handle,
+            model: None,
+            timeout: Duration::from_secs(1),
+        })
     }
 }
 
 impl NiimbotPrinterAdapter for UsbAdapter {
     fn send(&mut self, bytes: &[u8]) -> Result<usize> {
-        Ok(self
-            .device_handle
-            .write_bulk(0x01, bytes, Duration::from_secs(1))?)
+        Ok(self.device_handle.write_bulk(0x01, bytes, self.timeout)?)
     }
 
     fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
-        Ok(self
-            .device_handle
-            .read_bulk(0x81, bytes, Duration::from_secs(1))?)
+        Ok(self.device_handle.read_bulk(0x81, bytes, self.timeout)?)
+    }
+
+    fn model(&self) -> Option<PrinterModel> {
+        self.model
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+/// Raw packet passthrough to a printer attached to another machine, the other end is usually
+/// `niimbot-bridge`.
+pub struct TcpAdapter {
+    pub stream: TcpStream,
+}
+
+impl TcpAdapter {
+    pub fn new(address: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        Ok(Self { stream })
+    }
+}
+
+impl NiimbotPrinterAdapter for TcpAdapter {
+    fn send(&mut self, bytes: &[u8]) -> Result<usize> {
+        self.stream.write_all(bytes)?;
+        Ok(bytes.len())
+    }
+
+    fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        match self.stream.read(bytes)? {
+            0 => Err(NiimbotError::Disconnected),
+            len => Ok(len),
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.stream.set_write_timeout(Some(timeout))?;
+        Ok(())
+    }
+}
+
+/// Shuttle packets between a TCP client and a local adapter until the client goes away. The
+/// adapter should use a short read timeout since reads on both sides are polled in turn.
+///
+/// Packets from the client are reassembled first so the printer always gets whole packets in a
+/// single write, replies are passed through as they come.
+pub fn bridge(mut socket: TcpStream, adapter: &mut dyn NiimbotPrinterAdapter) -> Result<()> {
+    socket.set_nodelay(true)?;
+    socket.set_read_timeout(Some(Duration::from_millis(5)))?;
+    let mut framer = PacketFramer::new();
+    let mut buffer = [0u8; 1024];
+
+    loop {
+        match socket.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(len) => {
+                framer.push(&buffer[..len]);
+                while let Some(packet) = framer.next_packet() {
+                    match packet {
+                        Ok(packet) => {
+                            adapter.send(&packet.to_bytes())?;
+                        }
+                        Err(e) => log::warn!("Dropping bytes from bridge client: {e}"),
+                    }
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        match adapter.recv(&mut buffer) {
+            Ok(len) => socket.write_all(&buffer[..len])?,
+            Err(e) if e.is_timeout() => {}
+            Err(e) => return Err(e),
+        }
     }
 }
@@ -0,0 +1,333 @@
+//! Async counterpart of [`NiimbotPrinterClient`], behind the `async` feature.
+//!
+//! Replies are awaited with a timeout instead of sleeping between reads, so nothing blocks the
+//! runtime while the printer thinks.
+
+use crate::{
+    adapters::NiimbotPrinterAdapter,
+    events::{is_heartbeat, HeartbeatStatus, PrinterEvent},
+    framer::PacketFramer,
+    info::PrintStatus,
+    models::{PrintTaskVersion, PrinterModel},
+    retry::Timeouts,
+    ImageEncoding, NiimbotError, NiimbotPacket, NiimbotPrinterClient, Result,
+};
+use std::{
+    future::Future,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+
+pub trait AsyncNiimbotPrinterAdapter: Send {
+    fn send(&mut self, bytes: &[u8]) -> impl Future<Output = Result<usize>> + Send;
+    /// Wait for the next bytes from the printer, the client puts its own timeout around this.
+    fn recv(&mut self, bytes: &mut [u8]) -> impl Future<Output = Result<usize>> + Send;
+
+    fn model(&self) -> Option<PrinterModel> {
+        None
+    }
+}
+
+type BlockingRead = JoinHandle<(Result<usize>, Vec<u8>)>;
+
+/// Runs a blocking adapter on tokio's blocking thread pool, for USB and serial printers.
+pub struct BlockingAdapter<A> {
+    inner: Arc<Mutex<A>>,
+    model: Option<PrinterModel>,
+    /// A read that was still running when the `recv` waiting for it was dropped, e.g. by the
+    /// client's timeout. The next `recv` waits for it instead of starting another one, so its
+    /// bytes aren't lost.
+    pending: Option<BlockingRead>,
+    /// Bytes read that didn't fit into the caller's buffer yet.
+    unread: Vec<u8>,
+}
+
+impl<A: NiimbotPrinterAdapter + Send + 'static> BlockingAdapter<A> {
+    pub fn new(adapter: A) -> Self {
+        Self {
+            model: adapter.model(),
+            inner: Arc::new(Mutex::new(adapter)),
+            pending: None,
+            unread: Vec::new(),
+        }
+    }
+}
+
+impl<A: NiimbotPrinterAdapter + Send + 'static> AsyncNiimbotPrinterAdapter for BlockingAdapter<A> {
+    async fn send(&mut self, bytes: &[u8]) -> Result<usize> {
+        let inner = self.inner.clone();
+        let bytes = bytes.to_vec();
+        tokio::task::spawn_blocking(move || inner.lock().unwrap().send(&bytes))
+            .await
+            .map_err(|e| NiimbotError::Io(e.into()))?
+    }
+
+    async fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        if self.unread.is_empty() {
+            let pending = self.pending.get_or_insert_with(|| {
+                let inner = self.inner.clone();
+                let mut buffer = vec![0; bytes.len()];
+                tokio::task::spawn_blocking(move || {
+                    let len = inner.lock().unwrap().recv(&mut buffer);
+                    (len, buffer)
+                })
+            });
+            // awaiting a reference leaves the read in place when this future is dropped
+            let finished = pending.await;
+            self.pending = None;
+            let (len, mut buffer) = finished.map_err(|e| NiimbotError::Io(e.into()))?;
+            buffer.truncate(len?);
+            self.unread = buffer;
+        }
+        let len = bytes.len().min(self.unread.len());
+        bytes[..len].copy_from_slice(&self.unread[..len]);
+        self.unread.drain(..len);
+        Ok(len)
+    }
+
+    fn model(&self) -> Option<PrinterModel> {
+        self.model
+    }
+}
+
+/// Async version of [`crate::adapters::TcpAdapter`] for printers behind `niimbot-bridge`.
+pub struct AsyncTcpAdapter {
+    pub stream: TcpStream,
+}
+
+impl AsyncTcpAdapter {
+    pub async fn new(address: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream })
+    }
+}
+
+impl AsyncNiimbotPrinterAdapter for AsyncTcpAdapter {
+    async fn send(&mut self, bytes: &[u8]) -> Result<usize> {
+        self.stream.write_all(bytes).await?;
+        Ok(bytes.len())
+    }
+
+    async fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        match self.stream.read(bytes).await? {
+            0 => Err(NiimbotError::Disconnected),
+            len => Ok(len),
+        }
+    }
+}
+
+pub struct AsyncNiimbotPrinterClient<A> {
+    pub adapter: A,
+    pub image_encoding: ImageEncoding,
+    pub model: Option<PrinterModel>,
+    /// Only `command` and `status_poll` apply, replies are awaited instead of read again.
+    pub timeouts: Timeouts,
+    framer: PacketFramer,
+    status: HeartbeatStatus,
+    subscribers: Vec<Sender<PrinterEvent>>,
+}
+
+impl<A: AsyncNiimbotPrinterAdapter> AsyncNiimbotPrinterClient<A> {
+    pub fn new(adapter: A) -> Self {
+        Self {
+            model: adapter.model(),
+            adapter,
+            image_encoding: ImageEncoding::default(),
+            timeouts: Timeouts::default(),
+            framer: PacketFramer::new(),
+            status: HeartbeatStatus::default(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Same events as [`NiimbotPrinterClient::subscribe`].
+    pub fn subscribe(&mut self) -> Receiver<PrinterEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    fn emit(&mut self, event: PrinterEvent) {
+        self.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    fn update_status(&mut self, packet: &NiimbotPacket) -> HeartbeatStatus {
+        let status = HeartbeatStatus::from_packet(packet);
+        for event in status.changes(&self.status) {
+            self.emit(event);
+        }
+        self.status = HeartbeatStatus {
+            lid_open: status.lid_open.or(self.status.lid_open),
+            paper_out: status.paper_out.or(self.status.paper_out),
+            power_level: status.power_level.or(self.status.power_level),
+        };
+        status
+    }
+
+    fn handle_unsolicited(&mut self, packet: NiimbotPacket) {
+        match packet.packet_type {
+            t if is_heartbeat(t) => {
+                self.update_status(&packet);
+            }
+            0xd3 => self.emit(PrinterEvent::CheckLine(packet.data)),
+            _ => log::debug!("Ignoring packet {:?}", packet),
+        }
+    }
+
+    pub async fn send(&mut self, packet: NiimbotPacket) -> Result<()> {
+        self.adapter.send(&packet.to_bytes()).await?;
+        Ok(())
+    }
+
+    /// Wait for the reply to `request`, everything else that arrives meanwhile is handled as
+    /// unsolicited and error packets fail like in [`NiimbotPrinterClient::transceive`].
+    async fn wait_for(&mut self, request: u8, expected: u8) -> Result<NiimbotPacket> {
+        let mut buffer = [0u8; 1024];
+        loop {
+            while let Some(packet) = self.framer.next_packet() {
+                match packet {
+                    Ok(packet) if packet.packet_type == expected => return Ok(packet),
+                    Ok(packet) if packet.packet_type == 0xdb => {
+                        let code = packet.data.first().copied().unwrap_or_default();
+                        return Err(NiimbotError::Printer { request, code });
+                    }
+                    Ok(packet) if packet.packet_type == 0x00 => {
+                        return Err(NiimbotError::Unsupported { request })
+                    }
+                    Ok(packet) => self.handle_unsolicited(packet),
+                    Err(e) => log::debug!("Dropped bytes from the printer: {e}"),
+                }
+            }
+            match self.adapter.recv(&mut buffer).await {
+                Ok(len) => self.framer.push(&buffer[..len]),
+                // blocking adapters give up on their own now and then, keep waiting
+                Err(e) if e.is_timeout() => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Send a request and wait up to the command timeout for its reply.
+    pub async fn transceive(
+        &mut self,
+        request_code: u8,
+        data: &[u8],
+        response_offset: u8,
+    ) -> Result<NiimbotPacket> {
+        self.send(NiimbotPacket {
+            packet_type: request_code,
+            data: data.to_vec(),
+        })
+        .await?;
+
+        let expected = request_code.wrapping_add(response_offset);
+        match timeout(self.timeouts.command, self.wait_for(request_code, expected)).await {
+            Ok(packet) => packet,
+            Err(_) => Err(NiimbotError::Timeout {
+                request: request_code,
+            }),
+        }
+    }
+
+    pub async fn heartbeat(&mut self) -> Result<HeartbeatStatus> {
+        let response = self.transceive(0xdc, &[0x01], 1).await?;
+        Ok(self.update_status(&response))
+    }
+
+    pub async fn get_print_status(&mut self) -> Result<PrintStatus> {
+        let data = match self.transceive(0xa3, &[0x01], 16).await {
+            Ok(response) => response.data,
+            Err(NiimbotError::Timeout { .. }) => return Ok(PrintStatus::NotResponding),
+            Err(e) => return Err(e),
+        };
+        if data.len() < 4 {
+            return Err(NiimbotError::InvalidResponse {
+                request: 0xa3,
+                reason: format!("print status is {} bytes", data.len()),
+            });
+        }
+        Ok(PrintStatus::Printing {
+            page: u16::from_be_bytes([data[0], data[1]]),
+            progress1: data[2],
+            progress2: data[3],
+        })
+    }
+
+    /// Same as [`NiimbotPrinterClient::print_label`].
+    pub async fn print_label(
+        &mut self,
+        image: &[u32],
+        width: usize,
+        height: usize,
+        label_qty: u8,
+        label_type: u8,
+        label_density: u8,
+    ) -> Result<()> {
+        let profile = self.model.unwrap_or(PrinterModel::B1).profile();
+        let density = label_density.clamp(1, profile.max_density);
+
+        self.transceive(0x23, &[label_type], 16).await?;
+        self.transceive(0x21, &[density], 16).await?;
+        let start = match profile.print_task {
+            PrintTaskVersion::V1 | PrintTaskVersion::V3 => vec![0x01],
+            PrintTaskVersion::V4 => {
+                let mut data = (label_qty as u16).to_be_bytes().to_vec();
+                data.extend([0x00; 5]);
+                data
+            }
+        };
+        self.transceive(0x01, &start, 1).await?;
+        self.transceive(0x03, &[0x01], 1).await?;
+
+        let (rows, cols) = ((height as u16).to_be_bytes(), (width as u16).to_be_bytes());
+        match profile.print_task {
+            PrintTaskVersion::V1 => {
+                self.transceive(0x13, &[rows, cols].concat(), 1).await?;
+                self.transceive(0x15, &[label_qty], 1).await?;
+            }
+            PrintTaskVersion::V3 | PrintTaskVersion::V4 => {
+                let copies = (label_qty as u16).to_be_bytes();
+                self.send(NiimbotPacket {
+                    packet_type: 0x13,
+                    data: [rows, cols, copies].concat(),
+                })
+                .await?;
+            }
+        }
+
+        let packets = match self.image_encoding {
+            ImageEncoding::Naive => NiimbotPrinterClient::naive_encoder(width, height, image),
+            ImageEncoding::Compressed => {
+                NiimbotPrinterClient::compressed_encoder(width, height, image)
+            }
+        };
+        for packet in packets {
+            self.send(packet).await?;
+        }
+        self.transceive(0xe3, &[0x01], 1).await?;
+
+        loop {
+            match self.get_print_status().await? {
+                PrintStatus::Printing { page, .. } if page >= label_qty as u16 => break,
+                PrintStatus::Printing { .. } => sleep(self.timeouts.status_poll).await,
+                PrintStatus::NotResponding => {
+                    log::warn!(
+                        "Printer stopped answering print status, assuming the label is done"
+                    );
+                    break;
+                }
+            }
+        }
+        self.transceive(0xf3, &[0x01], 1).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Exposes a locally attached printer over TCP for `niimbot::adapters::TcpAdapter`.
+//!
+//! Usage: `niimbot-bridge [--listen 0.0.0.0:9100] [--serial /dev/ttyUSB0]`, without `--serial`
+//! the first NIIMBOT USB device is used.
+
+use std::{env, net::TcpListener, time::Duration};
+
+use color_eyre::{eyre::anyhow, Result};
+use niimbot::{
+    adapters::{bridge, NiimbotPrinterAdapter, SerialPortAdapter},
+    get_usb_adapter,
+};
+
+fn open_printer(serial: Option<&str>) -> Result<Box<dyn NiimbotPrinterAdapter>> {
+    Ok(match serial {
+        Some(path) => {
+            let mut adapter = SerialPortAdapter::new(path)?;
+            adapter.serial_port.set_timeout(Duration::from_millis(10))?;
+            Box::new(adapter)
+        }
+        None => {
+            let mut adapter = get_usb_adapter()?;
+            adapter.timeout = Duration::from_millis(10);
+            Box::new(adapter)
+        }
+    })
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let mut listen = "0.0.0.0:9100".to_string();
+    let mut serial = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => {
+                listen = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--listen needs an address"))?
+            }
+            "--serial" => {
+                serial = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow!("--serial needs a path"))?,
+                )
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unknown argument {other}, expected --listen or --serial"
+                ))
+            }
+        }
+    }
+
+    let listener = TcpListener::bind(&listen)?;
+    println!("Bridging printer on {listen}");
+
+    // one client at a time, the printer can't make sense of interleaved packets anyway
+    for socket in listener.incoming() {
+        let socket = socket?;
+        let peer = socket.peer_addr()?;
+        println!("{peer} connected");
+
+        // reopen for every client so a printer that was power cycled in between still works
+        let result = open_printer(serial.as_deref())
+            .and_then(|mut printer| Ok(bridge(socket, &mut *printer)?));
+        match result {
+            Ok(()) => println!("{peer} disconnected"),
+            Err(e) => eprintln!("Bridge for {peer} failed: {e:?}"),
+        }
+    }
+    Ok(())
+}
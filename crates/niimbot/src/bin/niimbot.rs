@@ -0,0 +1,228 @@
+//! Talk to a printer from the terminal, to diagnose it or test print without the rest of the app.
+//!
+//! Usage: `niimbot [--address host:port | --serial /dev/ttyUSB0 | --usb SERIAL] <command>`
+//!
+//! Commands: `list`, `info`, `status`, `heartbeat`, `set-shutdown <minutes>`, `set-density <n>`,
+//! `set-speed <n>`, `sound <on|off>`, `calibrate <label type>`, `rfid` and
+//! `print <image.png|image.webp> [--density N] [--copies N] [--rotate 0|90|180|270]
+//! [--label-type N] [--dither NAME]`. Without a transport the first NIIMBOT USB device is used.
+
+use std::{env, fs::File, io::BufReader, path::Path};
+
+use color_eyre::{eyre::anyhow, Result};
+use niimbot::{
+    adapters::{NiimbotPrinterAdapter, SerialPortAdapter, TcpAdapter},
+    discovery::{list_printers, open_usb_printer},
+    job::PrintJob,
+    models::{PrintDirection, PrinterModel},
+    raster::{Dithering, Rotation},
+    settings::SoundType,
+    NiimbotPrinterClient,
+};
+
+const USAGE: &str = "Usage: niimbot [--address host:port | --serial PATH | --usb SERIAL] \
+<list|info|status|heartbeat|set-shutdown MINUTES|set-density N|set-speed N|sound on|off|\
+calibrate LABEL_TYPE|rfid|print IMAGE [--density N] [--copies N] \
+[--rotate DEGREES] [--label-type N] [--dither NAME]>";
+
+enum Transport {
+    Usb(Option<String>),
+    Serial(String),
+    Tcp(String),
+}
+
+fn open_printer(transport: &Transport) -> Result<NiimbotPrinterClient> {
+    let adapter: Box<dyn NiimbotPrinterAdapter> = match transport {
+        Transport::Usb(serial_number) => Box::new(open_usb_printer(serial_number.as_deref())?),
+        Transport::Serial(path) => Box::new(SerialPortAdapter::new(path)?),
+        Transport::Tcp(address) => Box::new(TcpAdapter::new(address.as_str())?),
+    };
+    let mut printer = NiimbotPrinterClient::new(adapter)?;
+    if printer.model.is_none() {
+        if let Err(e) = printer.detect_model() {
+            eprintln!("Could not detect printer model, assuming B1: {e}");
+        }
+    }
+    Ok(printer)
+}
+
+/// Value following a flag, parsed.
+fn value<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T> {
+    let value = args.next().ok_or_else(|| anyhow!("{flag} needs a value"))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid value {value} for {flag}"))
+}
+
+/// Decode a png or webp into a framebuffer, transparent pixels are white.
+fn load_image(path: &Path) -> Result<(Vec<u32>, usize, usize)> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let (data, channels, width, height) = match extension.as_deref() {
+        Some("png") => {
+            let mut decoder = png::Decoder::new(File::open(path)?);
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info()?;
+            let mut data = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut data)?;
+            data.truncate(info.buffer_size());
+            let channels = info.color_type.samples();
+            (data, channels, info.width as usize, info.height as usize)
+        }
+        Some("webp") => {
+            let mut decoder = image_webp::WebPDecoder::new(BufReader::new(File::open(path)?))?;
+            let (width, height) = decoder.dimensions();
+            let channels = if decoder.has_alpha() { 4 } else { 3 };
+            let mut data = vec![0; width as usize * height as usize * channels];
+            decoder.read_image(&mut data)?;
+            (data, channels, width as usize, height as usize)
+        }
+        _ => return Err(anyhow!("Only png and webp images can be printed")),
+    };
+
+    let image = data
+        .chunks_exact(channels)
+        .map(|pixel| {
+            let (rgb, alpha) = match pixel {
+                [l] => ([*l; 3], u8::MAX),
+                [l, a] => ([*l; 3], *a),
+                [r, g, b] => ([*r, *g, *b], u8::MAX),
+                [r, g, b, a, ..] => ([*r, *g, *b], *a),
+                [] => unreachable!("chunks are never empty"),
+            };
+            let over_white =
+                |c: u8| ((c as u32 * alpha as u32 + 255 * (255 - alpha as u32)) / 255) as u8;
+            u32::from_be_bytes([
+                0,
+                over_white(rgb[0]),
+                over_white(rgb[1]),
+                over_white(rgb[2]),
+            ])
+        })
+        .collect();
+    Ok((image, width, height))
+}
+
+fn print(printer: &mut NiimbotPrinterClient, mut args: impl Iterator<Item = String>) -> Result<()> {
+    let path = args.next().ok_or_else(|| anyhow!("print needs an image"))?;
+    let mut job = PrintJob::new().dithering(Dithering::Threshold(128));
+    let (mut copies, mut label_type) = (1, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--density" => job = job.density(value(&mut args, &arg)?),
+            "--copies" => copies = value(&mut args, &arg)?,
+            "--label-type" => label_type = Some(value(&mut args, &arg)?),
+            "--rotate" => {
+                let degrees = value(&mut args, &arg)?;
+                let rotation = Rotation::from_degrees(degrees)
+                    .ok_or_else(|| anyhow!("--rotate takes 0, 90, 180 or 270"))?;
+                job = job.rotation(rotation);
+            }
+            "--dither" => {
+                let name: String = value(&mut args, &arg)?;
+                let dithering = Dithering::from_name(&name)
+                    .ok_or_else(|| anyhow!("Unknown dithering {name}"))?;
+                job = job.dithering(dithering);
+            }
+            other => return Err(anyhow!("Unknown print option {other}")),
+        }
+    }
+
+    // the loaded roll knows its label type, ask it unless told otherwise
+    let label_type = match label_type {
+        Some(label_type) => label_type,
+        None => printer.get_rfid_info()?.map_or(1, |rfid| rfid.label_type),
+    };
+
+    let (image, width, height) = load_image(Path::new(&path))?;
+    let profile = printer.model.unwrap_or(PrinterModel::B1).profile();
+    let rotation = match profile.print_direction {
+        PrintDirection::Left => job.rotation.then(Rotation::Clockwise90),
+        PrintDirection::Top => job.rotation,
+    };
+    let (printed_width, _) = rotation.size(width, height);
+    if printed_width > profile.printhead_pixels {
+        eprintln!(
+            "Image is {printed_width} pixels wide, the printhead only has {}",
+            profile.printhead_pixels
+        );
+    }
+
+    let job = job
+        .label_type(label_type)
+        .page(image, width, height, copies);
+    let events = printer.subscribe();
+    let outcome = printer.print(&job)?;
+    for event in events.try_iter() {
+        println!("{event:?}");
+    }
+    println!("{outcome:?}");
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let mut transport = Transport::Usb(None);
+    let mut args = env::args().skip(1);
+    let command = loop {
+        let arg = args.next().ok_or_else(|| anyhow!(USAGE))?;
+        match arg.as_str() {
+            "--address" => transport = Transport::Tcp(value(&mut args, &arg)?),
+            "--serial" => transport = Transport::Serial(value(&mut args, &arg)?),
+            "--usb" => transport = Transport::Usb(Some(value(&mut args, &arg)?)),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => break arg,
+        }
+    };
+
+    if command == "list" {
+        let printers = list_printers()?;
+        for printer in printers.usb {
+            println!("usb {printer:?}");
+        }
+        for printer in printers.serial {
+            println!("serial {printer:?}");
+        }
+        return Ok(());
+    }
+
+    let mut printer = open_printer(&transport)?;
+    match command.as_str() {
+        "info" => {
+            println!("model: {:?}", printer.model);
+            println!("{:#?}", printer.get_printer_info()?);
+        }
+        "status" => println!("{:?}", printer.get_print_status()?),
+        "heartbeat" => println!("{:?}", printer.heartbeat()?),
+        "set-shutdown" => {
+            let time = value(&mut args, "set-shutdown")?;
+            printer.set_autoshutdown_time(time)?;
+        }
+        "set-density" => printer.set_density(value(&mut args, "set-density")?)?,
+        "set-speed" => printer.set_speed(value(&mut args, "set-speed")?)?,
+        "sound" => {
+            let enabled = match value::<String>(&mut args, "sound")?.as_str() {
+                "on" => true,
+                "off" => false,
+                other => return Err(anyhow!("sound takes on or off, not {other}")),
+            };
+            for sound in [SoundType::BluetoothConnection, SoundType::Power] {
+                printer.set_sound(sound, enabled)?;
+            }
+        }
+        "calibrate" => printer.calibrate(value(&mut args, "calibrate")?)?,
+        "rfid" => match printer.get_rfid_info()? {
+            Some(rfid) => println!("{rfid:#?}\nremaining labels: {}", rfid.remaining_labels()),
+            None => println!("No tagged label roll loaded"),
+        },
+        "print" => print(&mut printer, args)?,
+        other => return Err(anyhow!("Unknown command {other}\n{USAGE}")),
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::{is_black, NiimbotError, NiimbotPacket, Result};
+
+/// A single image row as sent to the printer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedRow {
+    pub row: u16,
+    /// Black pixels in the left half according to the header.
+    pub left: usize,
+    /// Black pixels in the right half according to the header.
+    pub right: usize,
+    pub repeat: u16,
+    pub data: Vec<u8>,
+}
+
+impl DecodedRow {
+    /// Decode a 0x85 image row packet, `width` is needed to turn the header back into counts.
+    ///
+    /// The header stores `width / 2 - count` in a single byte so counts are only exact as long
+    /// as they stay below 256 per half.
+    pub fn from_packet(packet: &NiimbotPacket, width: usize) -> Result<Self> {
+        if packet.packet_type != 0x85 {
+            return Err(NiimbotError::InvalidImageData(format!(
+                "Not an image row packet: {:#x}",
+                packet.packet_type
+            )));
+        }
+        let data = &packet.data;
+        if data.len() < 6 {
+            return Err(NiimbotError::InvalidImageData(format!(
+                "Image row too short: {} bytes",
+                data.len()
+            )));
+        }
+
+        let mid_point = (width / 2) as i32;
+        let count = |byte: u8| (mid_point - byte as i32).rem_euclid(256) as usize;
+
+        Ok(Self {
+            row: u16::from_be_bytes([data[0], data[1]]),
+            left: count(data[2]),
+            right: count(data[3]),
+            repeat: u16::from_be_bytes([data[4], data[5]]),
+            data: data[6..].to_vec(),
+        })
+    }
+
+    pub fn pixel(&self, x: usize) -> bool {
+        self.data
+            .get(x / 8)
+            .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+
+    /// Black pixels in the left and right half counted from the row data itself.
+    pub fn black_pixels(&self, width: usize) -> (usize, usize) {
+        let mid_point = width / 2;
+        let left = (0..mid_point).filter(|&x| self.pixel(x)).count();
+        let right = (mid_point..width).filter(|&x| self.pixel(x)).count();
+        (left, right)
+    }
+}
+
+/// A 1-bit raster rebuilt from the packets sent to the printer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedImage {
+    pub width: usize,
+    pub height: usize,
+    pub rows: Vec<DecodedRow>,
+    /// `true` for black, row major.
+    pub pixels: Vec<bool>,
+}
+
+impl DecodedImage {
+    /// Rebuild the raster from a packet capture, packets that are not image data are skipped so a
+    /// full print session can be passed in.
+    ///
+    /// Empty rows (0x84) and repeated rows (0x86) from the compressed encoder are expanded into
+    /// regular rows with their `repeat` set to the run length.
+    pub fn decode(packets: &[NiimbotPacket], width: usize) -> Result<Self> {
+        let mut rows: Vec<DecodedRow> = Vec::new();
+        for packet in packets {
+            match packet.packet_type {
+                0x85 => rows.push(DecodedRow::from_packet(packet, width)?),
+                0x84 | 0x86 => {
+                    let data = &packet.data;
+                    if data.len() < 3 {
+                        return Err(NiimbotError::InvalidImageData(format!(
+                            "Row run too short: {} bytes",
+                            data.len()
+                        )));
+                    }
+                    let row = u16::from_be_bytes([data[0], data[1]]);
+                    let repeat = data[2] as u16;
+
+                    let decoded = if packet.packet_type == 0x84 {
+                        DecodedRow {
+                            row,
+                            left: 0,
+                            right: 0,
+                            repeat,
+                            data: vec![0; width.div_ceil(8)],
+                        }
+                    } else {
+                        let Some(previous) = rows.last() else {
+                            return Err(NiimbotError::InvalidImageData(format!(
+                                "Repeated row {row} without a row to repeat"
+                            )));
+                        };
+                        DecodedRow {
+                            row,
+                            repeat,
+                            ..previous.clone()
+                        }
+                    };
+                    rows.push(decoded);
+                }
+                _ => {}
+            }
+        }
+
+        let height = rows
+            .iter()
+            .map(|r| r.row as usize + r.repeat.max(1) as usize)
+            .max()
+            .unwrap_or(0);
+
+        let mut pixels = vec![false; width * height];
+        for row in &rows {
+            for y in row.row as usize..row.row as usize + row.repeat.max(1) as usize {
+                for x in 0..width {
+                    pixels[y * width + x] = row.pixel(x);
+                }
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            rows,
+            pixels,
+        })
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Turn the raster back into the framebuffer format used by the window.
+    pub fn to_framebuffer(&self) -> Vec<u32> {
+        self.pixels
+            .iter()
+            .map(|&black| if black { u32::MIN } else { u32::MAX })
+            .collect()
+    }
+
+    /// Coordinates where the printed raster differs from `image`, pixels that only exist on one
+    /// side (e.g. rows the printer never received) always count as different.
+    pub fn diff(&self, image: &[u32]) -> Vec<(usize, usize)> {
+        (0..self.pixels.len().max(image.len()))
+            .filter(|&i| self.pixels.get(i).copied() != image.get(i).map(|&p| is_black(p)))
+            .map(|i| (i % self.width, i / self.width))
+            .collect()
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{adapters::UsbAdapter, models::PrinterModel};
+use crate::{NiimbotError, Result};
+use rusb::{Device, GlobalContext};
+use serialport::SerialPortType;
+use std::time::Duration;
+
+/// All niimbot printers share this USB vendor id, the product id tells the models apart.
+pub const NIIMBOT_VENDOR_ID: u16 = 0x3513;
+
+/// A printer attached over USB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbPrinter {
+    pub bus: u8,
+    pub address: u8,
+    pub product_id: u16,
+    /// `None` when the device could not be opened to read it, usually missing permissions.
+    pub serial_number: Option<String>,
+    pub model: Option<PrinterModel>,
+}
+
+/// A serial port that could have a printer behind it, either a USB printer that shows up as a
+/// serial device or a bound bluetooth port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialPrinter {
+    pub port: String,
+    pub product_id: Option<u16>,
+    pub serial_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrinterList {
+    pub usb: Vec<UsbPrinter>,
+    pub serial: Vec<SerialPrinter>,
+}
+
+fn niimbot_devices() -> Result<Vec<Device<GlobalContext>>> {
+    Ok(rusb::devices()?
+        .iter()
+        .filter(|d| {
+            d.device_descriptor()
+                .map(|desc| desc.vendor_id() == NIIMBOT_VENDOR_ID)
+                .unwrap_or(false)
+        })
+        .collect())
+}
+
+fn usb_serial_number(device: &Device<GlobalContext>) -> Option<String> {
+    let desc = device.device_descriptor().ok()?;
+    let handle = device.open().ok()?;
+    let language = *handle
+        .read_languages(Duration::from_secs(1))
+        .ok()?
+        .first()?;
+    handle
+        .read_serial_number_string(language, &desc, Duration::from_secs(1))
+        .ok()
+}
+
+/// Every printer that can be found without talking to it.
+pub fn list_printers() -> Result<PrinterList> {
+    let usb = niimbot_devices()?
+        .iter()
+        .map(|device| {
+            let product_id = device
+                .device_descriptor()
+                .map(|desc| desc.product_id())
+                .unwrap_or_default();
+            UsbPrinter {
+                bus: device.bus_number(),
+                address: device.address(),
+                product_id,
+                serial_number: usb_serial_number(device),
+                model: PrinterModel::from_usb_product_id(product_id),
+            }
+        })
+        .collect();
+
+    let serial = serialport::available_ports()?
+        .into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(info) if info.vid == NIIMBOT_VENDOR_ID => Some(SerialPrinter {
+                port: port.port_name,
+                product_id: Some(info.pid),
+                serial_number: info.serial_number,
+            }),
+            SerialPortType::BluetoothPort => Some(SerialPrinter {
+                port: port.port_name,
+                product_id: None,
+                serial_number: None,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    Ok(PrinterList { usb, serial })
+}
+
+/// Open a USB printer, the one with the given serial number or otherwise the first one found.
+pub fn open_usb_printer(serial_number: Option<&str>) -> Result<UsbAdapter> {
+    let devices = niimbot_devices()?;
+    let device = match serial_number {
+        Some(serial_number) => devices
+            .into_iter()
+            .find(|d| usb_serial_number(d).as_deref() == Some(serial_number))
+            .ok_or_else(|| NiimbotError::NotFound {
+                serial_number: Some(serial_number.to_string()),
+            })?,
+        None => devices.into_iter().next().ok_or(NiimbotError::NotFound {
+            serial_number: None,
+        })?,
+    };
+
+    let handle = device.open()?;
+    if handle.kernel_driver_active(0)? {
+        handle.detach_kernel_driver(0)?;
+    }
+    handle.claim_interface(0)?;
+    let mut adapter = UsbAdapter::new(handle)?;
+    adapter.model = device
+        .device_descriptor()
+        .ok()
+        .and_then(|desc| PrinterModel::from_usb_product_id(desc.product_id()));
+    Ok(adapter)
+}
@@ -0,0 +1,195 @@
+use crate::framer::FrameError;
+use std::io::ErrorKind;
+
+/// Everything that can go wrong talking to a printer, see [`NiimbotError::needs_reconnect`] for
+/// telling a broken connection apart from a printer that is fine but unhappy.
+#[derive(Debug)]
+pub enum NiimbotError {
+    Io(std::io::Error),
+    Usb(rusb::Error),
+    Serial(serialport::Error),
+    /// The USB device went away or the bridge closed the connection.
+    Disconnected,
+    /// No printer found, or none with the requested serial number.
+    NotFound {
+        serial_number: Option<String>,
+    },
+    /// Bytes from or for the printer that don't make a valid packet.
+    Frame(FrameError),
+    /// Nothing came back for the request within the command timeout.
+    Timeout {
+        request: u8,
+    },
+    /// The printer answered the request with a packet of another type.
+    UnexpectedResponse {
+        request: u8,
+        expected: u8,
+        actual: u8,
+    },
+    /// The printer answered the request with a 0xdb error packet.
+    Printer {
+        request: u8,
+        code: u8,
+    },
+    /// The printer answered with a 0x00 packet, it doesn't know the request.
+    Unsupported {
+        request: u8,
+    },
+    /// The reply had the right type but not the data it should have.
+    InvalidResponse {
+        request: u8,
+        reason: String,
+    },
+    UnknownDeviceType(u16),
+    /// Connecting failed `attempts` times in a row, `source` is the last failure.
+    Unreachable {
+        attempts: u32,
+        source: Box<NiimbotError>,
+    },
+    /// A queued print job failed `attempts` times and was dropped.
+    JobFailed {
+        attempts: u32,
+        source: Box<NiimbotError>,
+    },
+    /// A line of a trace file that can't be parsed, or a trace that ran out during replay.
+    InvalidTrace(String),
+    /// Image packets that don't decode, see [`crate::decoder`].
+    InvalidImageData(String),
+}
+
+pub type Result<T, E = NiimbotError> = std::result::Result<T, E>;
+
+impl NiimbotError {
+    /// Whether this is just a read that found nothing before the adapter's timeout.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            NiimbotError::Usb(e) => *e == rusb::Error::Timeout,
+            NiimbotError::Io(e) => matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock),
+            NiimbotError::Serial(e) => matches!(
+                e.kind(),
+                serialport::ErrorKind::Io(ErrorKind::TimedOut | ErrorKind::WouldBlock)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the connection itself is broken or out of sync, printer errors, unsupported
+    /// commands and odd replies come over a connection that still works.
+    pub fn needs_reconnect(&self) -> bool {
+        match self {
+            NiimbotError::Io(_)
+            | NiimbotError::Usb(_)
+            | NiimbotError::Serial(_)
+            | NiimbotError::Disconnected
+            | NiimbotError::NotFound { .. }
+            | NiimbotError::Frame(_)
+            | NiimbotError::Timeout { .. }
+            | NiimbotError::Unreachable { .. } => true,
+            NiimbotError::JobFailed { source, .. } => source.needs_reconnect(),
+            NiimbotError::UnexpectedResponse { .. }
+            | NiimbotError::Printer { .. }
+            | NiimbotError::Unsupported { .. }
+            | NiimbotError::InvalidResponse { .. }
+            | NiimbotError::UnknownDeviceType(_)
+            | NiimbotError::InvalidTrace(_)
+            | NiimbotError::InvalidImageData(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for NiimbotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NiimbotError::Io(e) => write!(f, "{e}"),
+            NiimbotError::Usb(e) => write!(f, "USB: {e}"),
+            NiimbotError::Serial(e) => write!(f, "Serial port: {e}"),
+            NiimbotError::Disconnected => write!(f, "Printer disconnected"),
+            NiimbotError::NotFound {
+                serial_number: Some(serial_number),
+            } => write!(f, "No Niimbot with serial number {serial_number} found"),
+            NiimbotError::NotFound {
+                serial_number: None,
+            } => write!(f, "No Niimbot found"),
+            NiimbotError::Frame(e) => write!(f, "{e}"),
+            NiimbotError::Timeout { request } => write!(f, "No response to {request:#x}"),
+            NiimbotError::UnexpectedResponse {
+                request,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Printer answered {request:#x} with {actual:#x} instead of {expected:#x}"
+            ),
+            NiimbotError::Printer { request, code } => {
+                write!(f, "Printer reported error {code:#x} for {request:#x}")
+            }
+            NiimbotError::Unsupported { request } => {
+                write!(f, "Printer does not support {request:#x}")
+            }
+            NiimbotError::InvalidResponse { request, reason } => {
+                write!(f, "Invalid response to {request:#x}: {reason}")
+            }
+            NiimbotError::UnknownDeviceType(device_type) => {
+                write!(f, "Unknown device type {device_type}")
+            }
+            NiimbotError::Unreachable { attempts, source } => {
+                write!(f, "Printer unreachable after {attempts} attempts: {source}")
+            }
+            NiimbotError::JobFailed { attempts, source } => {
+                write!(f, "Dropping print job after {attempts} attempts: {source}")
+            }
+            NiimbotError::InvalidTrace(reason) => write!(f, "Invalid trace: {reason}"),
+            NiimbotError::InvalidImageData(reason) => write!(f, "Invalid image data: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for NiimbotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NiimbotError::Io(e) => Some(e),
+            NiimbotError::Usb(e) => Some(e),
+            NiimbotError::Serial(e) => Some(e),
+            NiimbotError::Frame(e) => Some(e),
+            NiimbotError::Unreachable { source, .. } | NiimbotError::JobFailed { source, .. } => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for NiimbotError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                NiimbotError::Disconnected
+            }
+            _ => NiimbotError::Io(error),
+        }
+    }
+}
+
+impl From<rusb::Error> for NiimbotError {
+    fn from(error: rusb::Error) -> Self {
+        match error {
+            rusb::Error::NoDevice => NiimbotError::Disconnected,
+            error => NiimbotError::Usb(error),
+        }
+    }
+}
+
+impl From<serialport::Error> for NiimbotError {
+    fn from(error: serialport::Error) -> Self {
+        match error.kind() {
+            serialport::ErrorKind::NoDevice => NiimbotError::Disconnected,
+            _ => NiimbotError::Serial(error),
+        }
+    }
+}
+
+impl From<FrameError> for NiimbotError {
+    fn from(error: FrameError) -> Self {
+        NiimbotError::Frame(error)
+    }
+}
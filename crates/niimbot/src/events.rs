@@ -0,0 +1,77 @@
+use crate::{supervisor::ConnectionState, NiimbotPacket};
+
+/// Things the printer reports on its own, delivered to everyone that called
+/// [`crate::NiimbotPrinterClient::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrinterEvent {
+    LidOpened,
+    LidClosed,
+    PaperOut,
+    PaperLoaded,
+    /// Printer check line notification (0xd3), the payload is passed on as is.
+    CheckLine(Vec<u8>),
+    /// Another label of the running [`crate::job::PrintJob`] came out.
+    PrintProgress {
+        printed: u16,
+        total: u16,
+    },
+    /// Only sent by [`crate::supervisor::Supervisor`].
+    Connection(ConnectionState),
+}
+
+/// Printer state carried by heartbeat replies, fields the reply doesn't have are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeartbeatStatus {
+    pub lid_open: Option<bool>,
+    pub paper_out: Option<bool>,
+    pub power_level: Option<u8>,
+}
+
+impl HeartbeatStatus {
+    /// Parse any of the heartbeat replies, the layout depends on the payload length (see
+    /// NiimBlue's heartbeat responses).
+    pub fn from_packet(packet: &NiimbotPacket) -> Self {
+        let data = &packet.data;
+        let flag = |index: usize| data.get(index).map(|&b| b != 0);
+        match data.len() {
+            20 => Self {
+                paper_out: flag(18),
+                ..Default::default()
+            },
+            13 => Self {
+                lid_open: flag(9),
+                power_level: data.get(10).copied(),
+                paper_out: flag(11),
+            },
+            10 => Self {
+                lid_open: flag(8),
+                power_level: data.get(9).copied(),
+                paper_out: None,
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Events for everything that changed since `previous`.
+    pub fn changes(&self, previous: &HeartbeatStatus) -> Vec<PrinterEvent> {
+        let mut events = Vec::new();
+        if self.lid_open.is_some() && self.lid_open != previous.lid_open {
+            events.push(match self.lid_open {
+                Some(true) => PrinterEvent::LidOpened,
+                _ => PrinterEvent::LidClosed,
+            });
+        }
+        if self.paper_out.is_some() && self.paper_out != previous.paper_out {
+            events.push(match self.paper_out {
+                Some(true) => PrinterEvent::PaperOut,
+                _ => PrinterEvent::PaperLoaded,
+            });
+        }
+        events
+    }
+}
+
+/// Heartbeat replies, only 0xdd answers the heartbeat we send but printers also push the others.
+pub(crate) fn is_heartbeat(packet_type: u8) -> bool {
+    matches!(packet_type, 0xd9 | 0xdd | 0xde | 0xdf)
+}
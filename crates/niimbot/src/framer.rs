@@ -0,0 +1,134 @@
+use crate::NiimbotPacket;
+
+/// Everything that can go wrong while cutting a byte stream into packets, the framer recovers
+/// from all of them by itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// Bytes that were not part of a packet were thrown away while looking for a 0x55 0x55
+    /// header.
+    Garbage(usize),
+    /// The header and length looked fine but the packet did not end in 0xaa 0xaa.
+    InvalidTrailer { packet_type: u8 },
+    InvalidChecksum {
+        packet_type: u8,
+        expected: u8,
+        actual: u8,
+    },
+    /// A single packet that doesn't start with 0x55 0x55 or is too short to be one.
+    InvalidHeader,
+    /// A single packet whose length byte doesn't match its size.
+    InvalidLength {
+        packet_type: u8,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Garbage(len) => write!(f, "Skipped {len} bytes of garbage"),
+            FrameError::InvalidTrailer { packet_type } => {
+                write!(f, "Packet {packet_type:#x} has no 0xaa 0xaa trailer")
+            }
+            FrameError::InvalidChecksum {
+                packet_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Packet {packet_type:#x} has checksum {actual:#x}, expected {expected:#x}"
+            ),
+            FrameError::InvalidHeader => write!(f, "Packet has no 0x55 0x55 header"),
+            FrameError::InvalidLength {
+                packet_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Packet {packet_type:#x} is {actual} bytes, expected {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Packet header, type, length, checksum and trailer.
+const OVERHEAD: usize = 7;
+
+pub(crate) fn checksum(packet_type: u8, data: &[u8]) -> u8 {
+    packet_type ^ (data.len() as u8) ^ data.iter().fold(0, |acc, x| acc ^ x)
+}
+
+/// Turns a stream of reads into packets, bytes are kept around until a packet is complete so
+/// packets split over several reads survive.
+///
+/// The length byte decides where a packet ends, so 0xaa 0xaa inside a payload is fine. After
+/// garbage or a broken packet the framer resynchronises on the next 0x55 0x55.
+#[derive(Debug, Default)]
+pub struct PacketFramer {
+    buffer: Vec<u8>,
+}
+
+impl PacketFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Bytes waiting for the rest of their packet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The next packet in the stream, `None` once more bytes are needed.
+    pub fn next_packet(&mut self) -> Option<Result<NiimbotPacket, FrameError>> {
+        let start = self.buffer.windows(2).position(|w| w == [0x55, 0x55]);
+
+        let skip = match start {
+            Some(start) => start,
+            // a trailing 0x55 could be the first half of the next header
+            None if self.buffer.last() == Some(&0x55) => self.buffer.len() - 1,
+            None => self.buffer.len(),
+        };
+        if skip > 0 {
+            self.buffer.drain(..skip);
+            return Some(Err(FrameError::Garbage(skip)));
+        }
+        start?;
+
+        if self.buffer.len() < 4 {
+            return None;
+        }
+        let packet_type = self.buffer[2];
+        let len = self.buffer[3] as usize;
+        let total = len + OVERHEAD;
+        if self.buffer.len() < total {
+            return None;
+        }
+
+        if self.buffer[total - 2..total] != [0xaa, 0xaa] {
+            // not a real header, look for the next one
+            self.buffer.drain(..1);
+            return Some(Err(FrameError::InvalidTrailer { packet_type }));
+        }
+
+        let frame: Vec<u8> = self.buffer.drain(..total).collect();
+        let data = frame[4..4 + len].to_vec();
+        let expected = checksum(packet_type, &data);
+        let actual = frame[4 + len];
+        if expected != actual {
+            return Some(Err(FrameError::InvalidChecksum {
+                packet_type,
+                expected,
+                actual,
+            }));
+        }
+
+        Some(Ok(NiimbotPacket { packet_type, data }))
+    }
+}
@@ -0,0 +1,117 @@
+/// Keys for the 0x40 info command, the printer answers with packet type `0x40 + key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InfoKey {
+    Density = 1,
+    Speed = 2,
+    LabelType = 3,
+    LanguageType = 6,
+    AutoShutdownTime = 7,
+    DeviceType = 8,
+    SoftVersion = 9,
+    Battery = 10,
+    DeviceSerial = 11,
+    HardVersion = 12,
+}
+
+/// Everything the printer reports through the info command, keys the model doesn't answer are
+/// left as `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrinterInfo {
+    pub density: Option<u8>,
+    pub speed: Option<u8>,
+    pub label_type: Option<u8>,
+    pub auto_shutdown_time: Option<u8>,
+    pub device_type: Option<u16>,
+    pub serial_number: Option<String>,
+    pub soft_version: Option<f32>,
+    pub hard_version: Option<f32>,
+    pub battery: Option<u8>,
+}
+
+/// Big endian integer made out of all the bytes of an info response.
+pub(crate) fn info_to_int(data: &[u8]) -> u64 {
+    data.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+pub(crate) fn info_to_version(data: &[u8]) -> f32 {
+    info_to_int(data) as f32 / 100.0
+}
+
+pub(crate) fn info_to_serial(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintStatus {
+    Printing {
+        /// Pages finished so far.
+        page: u16,
+        progress1: u8,
+        progress2: u8,
+    },
+    /// The printer did not answer the status request, the B1 does this once it is done
+    /// printing but it also happens when it is gone.
+    NotResponding,
+}
+
+impl PrintStatus {
+    pub fn page(&self) -> Option<u16> {
+        match self {
+            PrintStatus::Printing { page, .. } => Some(*page),
+            PrintStatus::NotResponding => None,
+        }
+    }
+}
+
+/// The RFID tag of the loaded label roll, read with the 0x1A command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RfidInfo {
+    pub uuid: String,
+    pub barcode: String,
+    pub serial: String,
+    pub total_labels: u16,
+    pub used_labels: u16,
+    /// Value to pass as `label_type` when printing on this roll.
+    pub label_type: u8,
+}
+
+impl RfidInfo {
+    pub fn remaining_labels(&self) -> u16 {
+        self.total_labels.saturating_sub(self.used_labels)
+    }
+
+    /// Parse a 0x1B response, `None` when no tagged roll is loaded.
+    ///
+    /// Layout: 8 byte uuid, length prefixed barcode and serial, then total and used label counts
+    /// as big endian u16 and the label type.
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<Option<Self>, String> {
+        if data.first().copied().unwrap_or(0) == 0 {
+            return Ok(None);
+        }
+
+        let too_short = || format!("RFID response too short: {} bytes", data.len());
+        let uuid = data.get(..8).ok_or_else(too_short)?;
+        let mut position = 8;
+        let mut read_string = || {
+            let len = *data.get(position).ok_or_else(too_short)? as usize;
+            let bytes = data
+                .get(position + 1..position + 1 + len)
+                .ok_or_else(too_short)?;
+            position += 1 + len;
+            Ok::<_, String>(String::from_utf8_lossy(bytes).to_string())
+        };
+        let barcode = read_string()?;
+        let serial = read_string()?;
+
+        let rest = data.get(position..position + 5).ok_or_else(too_short)?;
+        Ok(Some(Self {
+            uuid: info_to_serial(uuid),
+            barcode,
+            serial,
+            total_labels: u16::from_be_bytes([rest[0], rest[1]]),
+            used_labels: u16::from_be_bytes([rest[2], rest[3]]),
+            label_type: rest[4],
+        }))
+    }
+}
@@ -0,0 +1,116 @@
+use crate::raster::{Dithering, Rotation};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// One page of a [`PrintJob`], printed `copies` times before the next page starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintPage {
+    pub image: Vec<u32>,
+    pub width: usize,
+    pub height: usize,
+    pub copies: u16,
+}
+
+/// Stops a running [`PrintJob`] from another thread, the job notices between rows and status
+/// polls.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobOutcome {
+    Completed,
+    Cancelled,
+}
+
+/// Everything that goes into one print, run it with [`crate::NiimbotPrinterClient::print`].
+///
+/// ```ignore
+/// let job = PrintJob::new()
+///     .page(front, 384, 240, 2)
+///     .page(back, 384, 240, 1)
+///     .label_type(1)
+///     .density(3)
+///     .rotation(Rotation::Clockwise90);
+/// printer.print(&job)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PrintJob {
+    pub pages: Vec<PrintPage>,
+    pub label_type: u8,
+    /// Clamped to what the printer supports, `None` uses the model's default density.
+    pub density: Option<u8>,
+    /// Left as the printer has it when `None`.
+    pub speed: Option<u8>,
+    /// Applied to every page before it is encoded, page sizes are sent rotated as well.
+    pub rotation: Rotation,
+    /// Converts the pages to black and white first, without it only pixels with a blue channel
+    /// of 0 are printed.
+    pub dithering: Option<Dithering>,
+    cancel: CancelHandle,
+}
+
+impl PrintJob {
+    pub fn new() -> Self {
+        Self {
+            label_type: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn page(mut self, image: Vec<u32>, width: usize, height: usize, copies: u16) -> Self {
+        self.pages.push(PrintPage {
+            image,
+            width,
+            height,
+            copies,
+        });
+        self
+    }
+
+    pub fn label_type(mut self, label_type: u8) -> Self {
+        self.label_type = label_type;
+        self
+    }
+
+    pub fn density(mut self, density: u8) -> Self {
+        self.density = Some(density);
+        self
+    }
+
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn dithering(mut self, dithering: Dithering) -> Self {
+        self.dithering = Some(dithering);
+        self
+    }
+
+    /// Handle to cancel the job while it prints, grab it before handing the job over.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Labels that come out of the printer, every copy of every page.
+    pub fn total_labels(&self) -> u16 {
+        self.pages.iter().map(|page| page.copies).sum()
+    }
+}
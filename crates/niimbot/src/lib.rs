@@ -1,53 +1,82 @@
 #![allow(dead_code)]
 
 use adapters::{NiimbotPrinterAdapter, UsbAdapter};
-use color_eyre::{eyre::anyhow, Result};
-use std::{thread::sleep, time::Duration};
+pub use error::{NiimbotError, Result};
+use events::{is_heartbeat, HeartbeatStatus, PrinterEvent};
+use framer::{FrameError, PacketFramer};
+use info::{
+    info_to_int, info_to_serial, info_to_version, InfoKey, PrintStatus, PrinterInfo, RfidInfo,
+};
+use job::{JobOutcome, PrintJob};
+use models::{PrintDirection, PrintTaskVersion, PrinterModel};
+use raster::{dither, pad_rows, rotate, Rotation};
+use retry::{RetryPolicy, Timeouts};
+use settings::SoundType;
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread::sleep,
+    time::Instant,
+};
 
 pub mod adapters;
-
+#[cfg(feature = "async")]
+pub mod async_client;
+pub mod decoder;
+pub mod discovery;
+pub mod error;
+pub mod events;
+pub mod framer;
+pub mod info;
+pub mod job;
+pub mod mock;
+pub mod models;
+pub mod raster;
+pub mod retry;
+pub mod settings;
+pub mod supervisor;
+pub mod trace;
+
+#[cfg(test)]
+mod tests;
+
+/// The first niimbot printer attached over USB, see [`discovery`] to pick a specific one.
 pub fn get_usb_adapter() -> Result<UsbAdapter> {
-    let devices = rusb::devices().unwrap();
-    // NIIMBOT B1: 3513:0002
-    let niimbot = devices.iter().find(|d| {
-        d.device_descriptor()
-            .map(|desc| desc.vendor_id() == 0x3513)
-            .unwrap_or(false)
-    });
-
-    match niimbot {
-        Some(device) => {
-            let handle = device.open()?;
-            if handle.kernel_driver_active(0)? {
-                handle.detach_kernel_driver(0)?;
-            }
-            handle.claim_interface(0)?;
-            Ok(UsbAdapter::new(handle)?)
-        }
-        None => Err(anyhow!("No Niimbot found")),
-    }
+    discovery::open_usb_printer(None)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NiimbotPacket {
     pub packet_type: u8,
     pub data: Vec<u8>,
 }
 
 impl NiimbotPacket {
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
-        if bytes[..2] != [0x55, 0x55] || bytes[bytes.len() - 2..] != [0xaa, 0xaa] {
-            return Err("Invalid packet boundaries".to_string());
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FrameError> {
+        if bytes.len() < 7 || bytes[..2] != [0x55, 0x55] {
+            return Err(FrameError::InvalidHeader);
         }
 
         let packet_type = bytes[2];
         let len = bytes[3] as usize;
+        if bytes.len() != len + 7 {
+            return Err(FrameError::InvalidLength {
+                packet_type,
+                expected: len + 7,
+                actual: bytes.len(),
+            });
+        }
+        if bytes[bytes.len() - 2..] != [0xaa, 0xaa] {
+            return Err(FrameError::InvalidTrailer { packet_type });
+        }
         let data = bytes[4..4 + len].to_vec();
 
-        let computed_checksum =
-            packet_type ^ (len as u8) ^ data.iter().copied().fold(0, |acc, x| acc ^ x);
-        if bytes[4 + len] != computed_checksum {
-            return Err("Invalid checksum".to_string());
+        let expected = framer::checksum(packet_type, &data);
+        if bytes[4 + len] != expected {
+            return Err(FrameError::InvalidChecksum {
+                packet_type,
+                expected,
+                actual: bytes[4 + len],
+            });
         }
 
         Ok(NiimbotPacket { packet_type, data })
@@ -57,17 +86,20 @@ impl NiimbotPacket {
         let mut bytes = vec![0x55, 0x55, self.packet_type, self.data.len() as u8];
         bytes.extend(&self.data);
 
-        let checksum: u8 =
-            self.packet_type ^ bytes[3] ^ self.data.iter().copied().fold(0, |acc, x| acc ^ x);
-        bytes.push(checksum);
+        bytes.push(framer::checksum(self.packet_type, &self.data));
         bytes.extend(&[0xaa, 0xaa]);
 
         bytes
     }
 }
 
+/// Whether a framebuffer pixel ends up black on the label.
+pub fn is_black(pixel: u32) -> bool {
+    pixel & 0xFF == 0
+}
+
 fn prepare_image(image: &[u32], width: usize, height: usize) -> Vec<Vec<u8>> {
-    if width % 8 != 0 {
+    if !width.is_multiple_of(8) {
         eprintln!("Image width not a multiple of 8");
     }
 
@@ -84,7 +116,7 @@ fn prepare_image(image: &[u32], width: usize, height: usize) -> Vec<Vec<u8>> {
         let mut right = 0;
 
         for (index, &pixel) in pixels.iter().enumerate() {
-            let bit = if pixel & 0xFF > 0 { '0' } else { '1' };
+            let bit = if is_black(pixel) { '1' } else { '0' };
 
             if bit == '1' {
                 if index < mid_point {
@@ -119,51 +151,77 @@ fn prepare_image(image: &[u32], width: usize, height: usize) -> Vec<Vec<u8>> {
     image_data
 }
 
+/// How image rows are sent to the printer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageEncoding {
+    /// One 0x85 packet for every row.
+    #[default]
+    Naive,
+    /// Blank rows are skipped with 0x84 packets and identical rows are repeated with 0x86
+    /// packets, mostly white labels need a fraction of the packets.
+    Compressed,
+}
+
 pub struct NiimbotPrinterClient {
     pub adapter: Box<dyn NiimbotPrinterAdapter>,
+    pub image_encoding: ImageEncoding,
+    /// Decides which packets `print_label` sends, printers that are not known are driven like a
+    /// B1.
+    pub model: Option<PrinterModel>,
+    pub retry_policy: RetryPolicy,
+    /// Change with [`Self::set_timeouts`] so the adapter gets its timeout as well.
+    timeouts: Timeouts,
+    framer: PacketFramer,
+    /// Last known lid and paper state, used to only report changes.
+    status: HeartbeatStatus,
+    subscribers: Vec<Sender<PrinterEvent>>,
 }
 
 impl NiimbotPrinterClient {
     pub fn new(adapter: Box<dyn NiimbotPrinterAdapter>) -> Result<Self> {
-        Ok(Self { adapter })
+        Ok(Self {
+            model: adapter.model(),
+            adapter,
+            image_encoding: ImageEncoding::default(),
+            retry_policy: RetryPolicy::default(),
+            timeouts: Timeouts::default(),
+            framer: PacketFramer::new(),
+            status: HeartbeatStatus::default(),
+            subscribers: Vec::new(),
+        })
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) -> Result<()> {
+        self.adapter.set_timeout(timeouts.adapter)?;
+        self.timeouts = timeouts;
+        Ok(())
     }
 
     pub fn send(&mut self, packet: NiimbotPacket) -> Result<usize> {
         let bytes = packet.to_bytes();
         self.adapter.send(&bytes)?;
-        std::thread::sleep(Duration::from_millis(10));
+        sleep(self.timeouts.send_delay);
         Ok(0)
     }
 
-    fn recv(&mut self) -> Result<Vec<NiimbotPacket>> {
+    /// Packets and framing errors in the order they arrived.
+    fn recv(&mut self) -> Result<Vec<Result<NiimbotPacket, FrameError>>> {
         let mut packets = Vec::new();
         let mut buffer = [0u8; 1024];
-        let start_bytes = [0x55, 0x55];
-        let end_bytes = [0xaa, 0xaa];
-        let mut packet_buffer = Vec::new();
 
         let bytes_read = self.adapter.recv(&mut buffer)?;
         // dbg!("Bytes read: {}", bytes_read);
-        let mut position = 0;
-        while position < bytes_read {
-            // Accumulate packets
-            packet_buffer.push(buffer[position]);
-            position += 1;
-
-            // Check if we have a potential packet start
-            if packet_buffer.len() >= 4
-                && packet_buffer[0..2] == start_bytes
-                && packet_buffer.ends_with(&end_bytes)
-            {
-                if let Ok(packet) = NiimbotPacket::from_bytes(&packet_buffer) {
-                    log::debug!("Packet Received {:?}", packet);
-                    packets.push(packet);
-                    packet_buffer.clear(); // Reset for the next packet
-                } else {
-                    // If invalid packet, clear buffer and continue searching
-                    packet_buffer.clear();
-                }
+        self.framer.push(&buffer[..bytes_read]);
+        while let Some(packet) = self.framer.next_packet() {
+            match &packet {
+                Ok(packet) => log::debug!("Packet Received {:?}", packet),
+                Err(e) => log::debug!("Dropped bytes from the printer: {e}"),
             }
+            packets.push(packet);
         }
         Ok(packets)
     }
@@ -178,16 +236,165 @@ impl NiimbotPrinterClient {
             .collect()
     }
 
-    pub fn heartbeat(&mut self) -> Result<()> {
-        self.transceive(220, &[0x01], 1)?;
-        Ok(())
+    /// Encoder that skips blank rows with 0x84 and repeats identical rows with 0x86, both take
+    /// the starting row and a count of up to 255 rows.
+    pub fn compressed_encoder(width: usize, height: usize, img: &[u32]) -> Vec<NiimbotPacket> {
+        let rows = prepare_image(img, width, height);
+        let mut packets = Vec::new();
+
+        let run_length = |start: usize, row: &[u8]| {
+            rows[start..]
+                .iter()
+                .take(u8::MAX as usize)
+                .take_while(|r| r[6..] == row[6..])
+                .count()
+        };
+
+        let mut y = 0;
+        while y < rows.len() {
+            let row = &rows[y];
+            if row[6..].iter().all(|&b| b == 0) {
+                let count = run_length(y, row);
+                let mut data = (y as u16).to_be_bytes().to_vec();
+                data.push(count as u8);
+                packets.push(NiimbotPacket {
+                    packet_type: 0x84,
+                    data,
+                });
+                y += count;
+                continue;
+            }
+
+            packets.push(NiimbotPacket {
+                packet_type: 0x85,
+                data: row.clone(),
+            });
+            y += 1;
+
+            let count = run_length(y, row);
+            if count > 0 {
+                let mut data = (y as u16).to_be_bytes().to_vec();
+                data.push(count as u8);
+                packets.push(NiimbotPacket {
+                    packet_type: 0x86,
+                    data,
+                });
+                y += count;
+            }
+        }
+
+        packets
+    }
+
+    pub fn encode_image(&self, width: usize, height: usize, img: &[u32]) -> Vec<NiimbotPacket> {
+        match self.image_encoding {
+            ImageEncoding::Naive => NiimbotPrinterClient::naive_encoder(width, height, img),
+            ImageEncoding::Compressed => {
+                NiimbotPrinterClient::compressed_encoder(width, height, img)
+            }
+        }
+    }
+
+    pub fn heartbeat(&mut self) -> Result<HeartbeatStatus> {
+        let response = self.transceive(220, &[0x01], 1)?;
+        Ok(self.update_status(&response))
+    }
+
+    /// Get a channel with every [`PrinterEvent`] from now on, events are only noticed while the
+    /// client is talking to the printer so keep sending heartbeats.
+    pub fn subscribe(&mut self) -> Receiver<PrinterEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    fn emit(&mut self, event: PrinterEvent) {
+        log::debug!("Printer event {:?}", event);
+        self.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
     }
 
-    pub fn get_info(&mut self, info_type: u8) -> Result<Vec<u8>> {
-        let response = self.transceive(64, &[info_type], 0)?;
+    fn update_status(&mut self, packet: &NiimbotPacket) -> HeartbeatStatus {
+        let status = HeartbeatStatus::from_packet(packet);
+        for event in status.changes(&self.status) {
+            self.emit(event);
+        }
+        self.status = HeartbeatStatus {
+            lid_open: status.lid_open.or(self.status.lid_open),
+            paper_out: status.paper_out.or(self.status.paper_out),
+            power_level: status.power_level.or(self.status.power_level),
+        };
+        status
+    }
+
+    /// Packets that arrive while waiting for another response, `false` for packets that are
+    /// not meant to come by themselves.
+    fn handle_unsolicited(&mut self, packet: NiimbotPacket) -> bool {
+        match packet.packet_type {
+            t if is_heartbeat(t) => {
+                self.update_status(&packet);
+            }
+            0xd3 => self.emit(PrinterEvent::CheckLine(packet.data)),
+            _ => {
+                log::debug!("Ignoring packet {:?}", packet);
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn get_info(&mut self, key: InfoKey) -> Result<Vec<u8>> {
+        let response = self.transceive(0x40, &[key as u8], key as u8)?;
         Ok(response.data)
     }
 
+    /// Ask the printer for its device type and remember the matching model.
+    pub fn detect_model(&mut self) -> Result<PrinterModel> {
+        let device_type = info_to_int(&self.get_info(InfoKey::DeviceType)?) as u16;
+        let model = PrinterModel::from_device_type(device_type)
+            .ok_or(NiimbotError::UnknownDeviceType(device_type))?;
+        self.model = Some(model);
+        Ok(model)
+    }
+
+    /// Read the RFID tag of the loaded label roll, `None` when the roll has no tag or nothing is
+    /// loaded.
+    pub fn get_rfid_info(&mut self) -> Result<Option<RfidInfo>> {
+        let response = self.transceive(0x1a, &[0x01], 1)?;
+        RfidInfo::from_bytes(&response.data).map_err(|reason| NiimbotError::InvalidResponse {
+            request: 0x1a,
+            reason,
+        })
+    }
+
+    /// Query all info keys, keys the printer doesn't answer are left empty.
+    pub fn get_printer_info(&mut self) -> Result<PrinterInfo> {
+        let mut query = |key| match self.get_info(key) {
+            Ok(data) if !data.is_empty() => Some(data),
+            Ok(_) => None,
+            Err(e) => {
+                log::debug!("Printer did not answer info {key:?}: {e:?}");
+                None
+            }
+        };
+
+        Ok(PrinterInfo {
+            density: query(InfoKey::Density).map(|d| info_to_int(&d) as u8),
+            speed: query(InfoKey::Speed).map(|d| info_to_int(&d) as u8),
+            label_type: query(InfoKey::LabelType).map(|d| info_to_int(&d) as u8),
+            auto_shutdown_time: query(InfoKey::AutoShutdownTime).map(|d| info_to_int(&d) as u8),
+            device_type: query(InfoKey::DeviceType).map(|d| info_to_int(&d) as u16),
+            serial_number: query(InfoKey::DeviceSerial).map(|d| info_to_serial(&d)),
+            soft_version: query(InfoKey::SoftVersion).map(|d| info_to_version(&d)),
+            hard_version: query(InfoKey::HardVersion).map(|d| info_to_version(&d)),
+            battery: query(InfoKey::Battery).map(|d| info_to_int(&d) as u8),
+        })
+    }
+
+    /// Send a request and wait for the reply of type `request_code + response_offset`.
+    ///
+    /// Error packets from the printer fail right away, a printer that doesn't answer in time is
+    /// [`NiimbotError::Timeout`] unless a garbled packet or a reply of the wrong type arrived
+    /// meanwhile, then that is the error.
     pub fn transceive(
         &mut self,
         request_code: u8,
@@ -200,25 +407,72 @@ impl NiimbotPrinterClient {
         };
 
         self.send(packet)?;
+        let started = Instant::now();
+        let expected = request_code.wrapping_add(response_offset);
+        let mut problem = None;
 
         // dbg!("Packet send");
 
-        // Simple retry loop for receiving the expected response
-        for _ in 0..5 {
-            if let Ok(response) = self.recv() {
-                for packet in response {
-                    // dbg!(&packet);
-                    if packet.packet_type == request_code + response_offset {
-                        return Ok(packet);
+        for attempt in 1..=self.retry_policy.attempts {
+            let response = match self.recv() {
+                Ok(response) => response,
+                Err(e) if e.is_timeout() => Vec::new(),
+                Err(e) => return Err(e),
+            };
+            let mut found = None;
+            for packet in response {
+                // dbg!(&packet);
+                let packet = match packet {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        problem = Some(NiimbotError::Frame(e));
+                        continue;
+                    }
+                };
+                match packet.packet_type {
+                    t if t == expected && found.is_none() => found = Some(packet),
+                    0xdb => {
+                        let code = packet.data.first().copied().unwrap_or_default();
+                        return Err(NiimbotError::Printer {
+                            request: request_code,
+                            code,
+                        });
+                    }
+                    0x00 => {
+                        return Err(NiimbotError::Unsupported {
+                            request: request_code,
+                        })
+                    }
+                    actual => {
+                        if !self.handle_unsolicited(packet) {
+                            problem = Some(NiimbotError::UnexpectedResponse {
+                                request: request_code,
+                                expected,
+                                actual,
+                            });
+                        }
                     }
                 }
             }
-            std::thread::sleep(Duration::from_millis(200));
+            if let Some(found) = found {
+                return Ok(found);
+            }
+
+            let delay = self.retry_policy.delay(attempt);
+            if attempt == self.retry_policy.attempts
+                || started.elapsed() + delay > self.timeouts.command
+            {
+                break;
+            }
+            sleep(delay);
         }
 
-        Err(anyhow!("No response"))?
+        Err(problem.unwrap_or(NiimbotError::Timeout {
+            request: request_code,
+        }))
     }
 
+    /// Print `label_qty` copies of a single image, see [`PrintJob`] for anything fancier.
     pub fn print_label(
         &mut self,
         image: &[u32],
@@ -228,54 +482,170 @@ impl NiimbotPrinterClient {
         label_type: u8,
         label_density: u8,
     ) -> Result<()> {
-        self.set_label_type(label_type)?;
-        self.set_label_density(label_density)?;
-        log::debug!("Starting print");
-        self.start_print()?;
-        log::debug!("Starting page print");
-        self.start_page_print()?;
-        log::debug!("Setting page size");
-
-        self.set_page_size_v3(height as u16, width as u16, label_qty as u16)?;
-
-        let packets = NiimbotPrinterClient::naive_encoder(width, height, image);
-        dbg!(packets.len());
-        for packet in packets {
-            self.send(packet)?;
+        let job = PrintJob::new()
+            .page(image.to_vec(), width, height, label_qty as u16)
+            .label_type(label_type)
+            .density(label_density);
+        self.print(&job).map(|_| ())
+    }
+
+    /// Run a whole print job, pages are sent one after another and every page waits until the
+    /// printer has finished all of its copies. [`PrinterEvent::PrintProgress`] is emitted every
+    /// time another label comes out.
+    pub fn print(&mut self, job: &PrintJob) -> Result<JobOutcome> {
+        let profile = self.model.unwrap_or(PrinterModel::B1).profile();
+        let cancel = job.cancel_handle();
+        let total = job.total_labels();
+
+        self.set_label_type(job.label_type)?;
+        let density = job.density.unwrap_or(profile.default_density);
+        self.set_density(density.clamp(1, profile.max_density))?;
+        if let Some(speed) = job.speed {
+            self.set_speed(speed)?;
         }
+        log::debug!(
+            "Starting print of {} pages, {total} labels",
+            job.pages.len()
+        );
+        self.start_print(profile.print_task, total)?;
+
+        let mut printed = 0;
+        let mut done = 0;
+        // left feeding printheads run along the label's height, turn the label to match
+        let rotation = match profile.print_direction {
+            PrintDirection::Left => job.rotation.then(Rotation::Clockwise90),
+            PrintDirection::Top => job.rotation,
+        };
+        for page in &job.pages {
+            let (mut image, width, height) = rotate(&page.image, page.width, page.height, rotation);
+            if let Some(dithering) = job.dithering {
+                image = dither(&image, width, height, dithering);
+            }
+            let (image, width) = pad_rows(image, width, height);
+            if width > profile.printhead_pixels {
+                log::warn!(
+                    "Image is {width} pixels wide but the {} printhead only has {}",
+                    profile.name,
+                    profile.printhead_pixels
+                );
+            }
 
-        log::debug!("Start Print");
-        self.end_page_print()?;
-        log::debug!("Get Status");
-        while self
-            .get_print_status(label_qty as usize)?
-            .get("page")
-            .copied()
-            .unwrap_or(0)
-            != label_qty.into()
-        {
-            sleep(Duration::from_millis(100));
+            self.start_page_print()?;
+            match profile.print_task {
+                PrintTaskVersion::V1 => {
+                    self.set_dimension(height as u16, width as u16)?;
+                    self.set_quantity(page.copies.min(u8::MAX as u16) as u8)?;
+                }
+                PrintTaskVersion::V3 | PrintTaskVersion::V4 => {
+                    self.set_page_size_v3(height as u16, width as u16, page.copies)?;
+                }
+            }
+
+            for packet in self.encode_image(width, height, &image) {
+                if cancel.is_cancelled() {
+                    return self.cancel_print();
+                }
+                self.send(packet)?;
+            }
+            self.end_page_print()?;
+
+            done += page.copies;
+            while printed < done {
+                if cancel.is_cancelled() {
+                    return self.cancel_print();
+                }
+                match self.get_print_status()? {
+                    PrintStatus::Printing { page, .. } => {
+                        if page > printed {
+                            printed = page.min(total);
+                            self.emit(PrinterEvent::PrintProgress { printed, total });
+                        }
+                        if printed < done {
+                            sleep(self.timeouts.status_poll);
+                        }
+                    }
+                    PrintStatus::NotResponding => {
+                        // dumbass printer stop responding to print status packets after its done printing but that is usually ver7 quickly
+                        log::warn!(
+                            "Printer stopped answering print status, assuming the page is done"
+                        );
+                        printed = done;
+                    }
+                }
+            }
         }
+
         log::debug!("End Print");
-        // self.end_print()?;
+        self.end_print()?;
+        Ok(JobOutcome::Completed)
+    }
 
-        Ok(())
+    /// Stop the current job, the printer throws away what it has not printed yet.
+    fn cancel_print(&mut self) -> Result<JobOutcome> {
+        log::info!("Cancelling print");
+        self.send(NiimbotPacket {
+            packet_type: 0xda,
+            data: vec![0x01],
+        })?;
+        self.end_print()?;
+        Ok(JobOutcome::Cancelled)
     }
 
     fn set_label_type(&mut self, label_type: u8) -> Result<()> {
         self.transceive(35, &[label_type], 16).map(|_| ())
     }
 
-    fn set_label_density(&mut self, density: u8) -> Result<()> {
+    /// Print density, also the default for prints that don't set their own.
+    pub fn set_density(&mut self, density: u8) -> Result<()> {
         self.transceive(33, &[density], 16).map(|_| ())
     }
 
-    fn start_print(&mut self) -> Result<()> {
-        self.transceive(1, &[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00], 1)
-            .map(|v| dbg!(v))
+    pub fn get_density(&mut self) -> Result<u8> {
+        Ok(info_to_int(&self.get_info(InfoKey::Density)?) as u8)
+    }
+
+    pub fn set_speed(&mut self, speed: u8) -> Result<()> {
+        self.transceive(0x22, &[speed], 16).map(|_| ())
+    }
+
+    pub fn get_speed(&mut self) -> Result<u8> {
+        Ok(info_to_int(&self.get_info(InfoKey::Speed)?) as u8)
+    }
+
+    /// Label positioning calibration, the printer feeds labels of the given type until it has
+    /// found the gap between them.
+    pub fn calibrate(&mut self, label_type: u8) -> Result<()> {
+        self.transceive(0x8e, &[label_type], 1).map(|_| ())
+    }
+
+    pub fn set_sound(&mut self, sound: SoundType, enabled: bool) -> Result<()> {
+        self.transceive(0x58, &[0x01, sound as u8, enabled as u8], 16)
             .map(|_| ())
     }
 
+    pub fn get_sound(&mut self, sound: SoundType) -> Result<bool> {
+        let response = self.transceive(0x58, &[0x02, sound as u8, 0x01], 16)?;
+        match response.data.get(2) {
+            Some(&enabled) => Ok(enabled != 0),
+            None => Err(NiimbotError::InvalidResponse {
+                request: 0x58,
+                reason: format!("sound setting is {} bytes", response.data.len()),
+            }),
+        }
+    }
+
+    fn start_print(&mut self, task: PrintTaskVersion, total_pages: u16) -> Result<()> {
+        let data = match task {
+            PrintTaskVersion::V1 | PrintTaskVersion::V3 => vec![0x01],
+            PrintTaskVersion::V4 => {
+                let mut data = total_pages.to_be_bytes().to_vec();
+                data.extend([0x00, 0x00, 0x00, 0x00, 0x00]);
+                data
+            }
+        };
+        self.transceive(1, &data, 1).map(|_| ())
+    }
+
     fn allow_print_clear(&mut self) -> Result<()> {
         self.transceive(32, &[0x01], 16).map(|_| ())
     }
@@ -325,40 +695,26 @@ impl NiimbotPrinterClient {
         self.transceive(0x27, &[time], 0x37 - 0x27).map(|_| ())
     }
 
-    pub fn get_print_status(
-        &mut self,
-        quantity: usize,
-    ) -> Result<std::collections::HashMap<String, usize>> {
-        // dumbass printer stop responding to print status packets after its done printing but that is usually ver7 quickly
-        let response = match self.transceive(0xb3, &[0x01], 16) {
-            Ok(d) => d,
-            Err(e) => {
-                log::error!("Failed to get print status: {:?}", e);
-                return Ok([
-                    ("page".into(), quantity),
-                    ("progress1".into(), 100),
-                    ("progress2".into(), 100),
-                ]
-                .iter()
-                .cloned()
-                .collect());
+    pub fn get_print_status(&mut self) -> Result<PrintStatus> {
+        let data = match self.transceive(0xa3, &[0x01], 16) {
+            Ok(response) => response.data,
+            Err(NiimbotError::Timeout { .. }) => {
+                log::debug!("Printer did not answer print status");
+                return Ok(PrintStatus::NotResponding);
             }
+            Err(e) => return Err(e),
         };
-        let data = response.data;
         if data.len() < 4 {
-            return Err(anyhow!("Invalid response"));
+            return Err(NiimbotError::InvalidResponse {
+                request: 0xa3,
+                reason: format!("print status is {} bytes", data.len()),
+            });
         }
-        let page = u16::from_be_bytes([data[0], data[1]]) as usize;
-        let progress1 = data[2] as usize;
-        let progress2 = data[3] as usize;
-
-        Ok([
-            ("page".into(), page),
-            ("progress1".into(), progress1),
-            ("progress2".into(), progress2),
-        ]
-        .iter()
-        .cloned()
-        .collect())
+
+        Ok(PrintStatus::Printing {
+            page: u16::from_be_bytes([data[0], data[1]]),
+            progress1: data[2],
+            progress2: data[3],
+        })
     }
 }
@@ -0,0 +1,340 @@
+use crate::{adapters::NiimbotPrinterAdapter, info::RfidInfo, NiimbotError, NiimbotPacket, Result};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Faults that can be scripted into the [`MockAdapter`], each one is consumed by the next reply
+/// the emulated printer generates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockFault {
+    /// The next `recv` call fails like a USB bulk read timing out, the reply stays queued.
+    Timeout,
+    /// The reply is sent with a corrupted checksum.
+    BadChecksum,
+    /// The reply is never sent.
+    DropReply,
+    /// A 0xdb error packet with this code is sent instead of the reply.
+    PrinterError(u8),
+}
+
+/// Everything the emulated printer has seen and is about to send back.
+#[derive(Debug, Default)]
+pub struct MockState {
+    /// Every packet the client sent, in order.
+    pub received: Vec<NiimbotPacket>,
+    /// Bytes waiting to be read by the client.
+    pub outgoing: VecDeque<u8>,
+    pub faults: VecDeque<MockFault>,
+    pending_timeouts: usize,
+    /// Every send fails like the USB device got unplugged.
+    pub disconnected: bool,
+    pub label_type: u8,
+    pub density: u8,
+    pub autoshutdown_time: u8,
+    /// Reported through the device type info key, see [`crate::models::MODELS`].
+    pub device_type: u16,
+    pub page_size: Option<(u16, u16, u16)>,
+    /// The tag of the loaded roll, `None` answers like a roll without a tag.
+    pub rfid: Option<RfidInfo>,
+    pub quantity: Option<u8>,
+    pub speed: u8,
+    /// Bluetooth connection and power sound, switched with 0x58.
+    pub sounds: [bool; 2],
+    /// Label type of the last label positioning calibration.
+    pub calibrated: Option<u8>,
+    /// Commands answered with a 0x00 packet, like a model that doesn't have them.
+    pub unsupported: Vec<u8>,
+    pub lid_open: bool,
+    pub paper_out: bool,
+    pub printing: bool,
+    pub pages_printed: u16,
+    /// Copies of all pages sent so far in the current job.
+    pub pages_queued: u16,
+    pub progress: u8,
+    pub cancelled: bool,
+}
+
+impl MockState {
+    /// The image packets (0x84, 0x85 and 0x86) the printer received, in order.
+    pub fn image_rows(&self) -> Vec<&NiimbotPacket> {
+        self.received
+            .iter()
+            .filter(|p| matches!(p.packet_type, 0x84..=0x86))
+            .collect()
+    }
+}
+
+/// A loopback adapter that answers like a NIIMBOT B1 would, so the client can be tested without
+/// a printer attached.
+///
+/// The adapter shares its [`MockState`] so tests can keep a handle to it after handing the
+/// adapter over to a [`crate::NiimbotPrinterClient`].
+#[derive(Clone)]
+pub struct MockAdapter {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl Default for MockAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockAdapter {
+    pub fn new() -> Self {
+        Self::with_device_type(4096)
+    }
+
+    /// Emulate another model, only the device type info and the page size parsing change.
+    pub fn with_device_type(device_type: u16) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                device_type,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Queue a fault that will be applied to the next reply.
+    pub fn inject(&self, fault: MockFault) {
+        self.state().faults.push_back(fault);
+    }
+
+    /// Queue a packet the printer sends on its own, the client sees it during its next read.
+    pub fn push_unsolicited(&self, packet: NiimbotPacket) {
+        self.state().outgoing.extend(packet.to_bytes());
+    }
+
+    fn reply(state: &mut MockState, packet_type: u8, data: Vec<u8>) {
+        let mut bytes = NiimbotPacket { packet_type, data }.to_bytes();
+
+        match state.faults.pop_front() {
+            Some(MockFault::DropReply) => {
+                log::debug!("Mock dropping reply {packet_type:#x}");
+                return;
+            }
+            Some(MockFault::BadChecksum) => {
+                let checksum = bytes.len() - 3;
+                bytes[checksum] ^= 0xff;
+            }
+            Some(MockFault::Timeout) => state.pending_timeouts += 1,
+            Some(MockFault::PrinterError(code)) => {
+                bytes = NiimbotPacket {
+                    packet_type: 0xdb,
+                    data: vec![code],
+                }
+                .to_bytes();
+            }
+            None => {}
+        }
+
+        state.outgoing.extend(bytes);
+    }
+
+    fn handle(state: &mut MockState, packet: &NiimbotPacket) {
+        let arg = packet.data.first().copied().unwrap_or(0);
+        if state.unsupported.contains(&packet.packet_type) {
+            Self::reply(state, 0x00, vec![1]);
+            return;
+        }
+        match packet.packet_type {
+            // heartbeat, the 13 byte variant with lid, battery, paper and rfid state
+            0xdc => {
+                let mut data = vec![0; 9];
+                data.extend([state.lid_open as u8, 4, state.paper_out as u8, 1]);
+                Self::reply(state, 0xdd, data);
+            }
+            // get info, replies with 0x40 + key
+            0x40 => {
+                let data = match arg {
+                    1 => vec![state.density],
+                    2 => vec![state.speed],
+                    3 => vec![state.label_type],
+                    7 => vec![state.autoshutdown_time],
+                    8 => state.device_type.to_be_bytes().to_vec(),
+                    // software and hardware version times 100
+                    9 => 512u16.to_be_bytes().to_vec(),
+                    10 => vec![4],
+                    11 => vec![0xb1, 0x00, 0x12, 0x34],
+                    12 => 110u16.to_be_bytes().to_vec(),
+                    _ => vec![0],
+                };
+                Self::reply(state, 0x40 + arg, data);
+            }
+            // rfid info
+            0x1a => {
+                let data = match &state.rfid {
+                    Some(rfid) => {
+                        let mut data = vec![0x01; 8];
+                        for text in [&rfid.barcode, &rfid.serial] {
+                            data.push(text.len() as u8);
+                            data.extend(text.as_bytes());
+                        }
+                        data.extend(rfid.total_labels.to_be_bytes());
+                        data.extend(rfid.used_labels.to_be_bytes());
+                        data.push(rfid.label_type);
+                        data
+                    }
+                    None => vec![0],
+                };
+                Self::reply(state, 0x1b, data);
+            }
+            // set density
+            0x21 => {
+                state.density = arg;
+                Self::reply(state, 0x31, vec![1]);
+            }
+            // set speed
+            0x22 => {
+                state.speed = arg;
+                Self::reply(state, 0x32, vec![1]);
+            }
+            // sound settings, 1 sets and 2 reads the sound given in the second byte
+            0x58 => {
+                let sound = packet.data.get(1).copied().unwrap_or(1).clamp(1, 2);
+                let slot = &mut state.sounds[sound as usize - 1];
+                if arg == 0x01 {
+                    *slot = packet.data.get(2).copied().unwrap_or(0) != 0;
+                }
+                let enabled = *slot as u8;
+                Self::reply(state, 0x68, vec![arg, sound, enabled]);
+            }
+            // label positioning calibration
+            0x8e => {
+                state.calibrated = Some(arg);
+                Self::reply(state, 0x8f, vec![1]);
+            }
+            // set label type
+            0x23 => {
+                state.label_type = arg;
+                Self::reply(state, 0x33, vec![1]);
+            }
+            // allow print clear
+            0x20 => Self::reply(state, 0x30, vec![1]),
+            // set autoshutdown time
+            0x27 => {
+                state.autoshutdown_time = arg;
+                Self::reply(state, 0x37, vec![1]);
+            }
+            // start print
+            0x01 => {
+                state.printing = true;
+                state.cancelled = false;
+                state.pages_printed = 0;
+                state.pages_queued = 0;
+                Self::reply(state, 0x02, vec![1]);
+            }
+            // start page print
+            0x03 => Self::reply(state, 0x04, vec![1]),
+            // set page size, older print tasks leave out the copies
+            0x13 => {
+                let d = &packet.data;
+                if d.len() >= 4 {
+                    let copies = match d.get(4..6) {
+                        Some(copies) => u16::from_be_bytes([copies[0], copies[1]]),
+                        // the quantity follows in its own packet
+                        None => 1,
+                    };
+                    if d.len() >= 6 {
+                        state.pages_queued += copies;
+                    }
+                    state.page_size = Some((
+                        u16::from_be_bytes([d[0], d[1]]),
+                        u16::from_be_bytes([d[2], d[3]]),
+                        copies,
+                    ));
+                }
+                Self::reply(state, 0x14, vec![1]);
+            }
+            // set quantity
+            0x15 => {
+                state.quantity = Some(arg);
+                state.pages_queued += arg as u16;
+                Self::reply(state, 0x16, vec![1]);
+            }
+            // empty, bitmap and repeated rows are not acknowledged
+            0x84..=0x86 => {}
+            // end page print
+            0xe3 => {
+                state.progress = 0;
+                Self::reply(state, 0xe4, vec![1]);
+            }
+            // print status, every poll moves the head further along the page until all queued
+            // copies are done
+            0xa3 => {
+                if state.printing && state.pages_printed < state.pages_queued {
+                    state.progress = (state.progress + 50).min(100);
+                    if state.progress == 100 {
+                        state.pages_printed += 1;
+                        if state.pages_printed < state.pages_queued {
+                            state.progress = 0;
+                        }
+                    }
+                }
+                let mut data = state.pages_printed.to_be_bytes().to_vec();
+                data.extend([state.progress, state.progress, 0, 0, 0, 0]);
+                Self::reply(state, 0xb3, data);
+            }
+            // cancel print, not acknowledged
+            0xda => {
+                state.printing = false;
+                state.cancelled = true;
+            }
+            // end print
+            0xf3 => {
+                state.printing = false;
+                Self::reply(state, 0xf4, vec![1]);
+            }
+            other => log::debug!("Mock ignoring unknown packet {other:#x}"),
+        }
+    }
+}
+
+impl NiimbotPrinterAdapter for MockAdapter {
+    fn send(&mut self, bytes: &[u8]) -> Result<usize> {
+        let packet = NiimbotPacket::from_bytes(bytes)?;
+        let mut state = self.state();
+        if state.disconnected {
+            return Err(NiimbotError::Disconnected);
+        }
+        Self::handle(&mut state, &packet);
+        state.received.push(packet);
+        Ok(bytes.len())
+    }
+
+    fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        let mut state = self.state();
+        if state.pending_timeouts > 0 || state.outgoing.is_empty() {
+            state.pending_timeouts = state.pending_timeouts.saturating_sub(1);
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+        }
+
+        let len = bytes.len().min(state.outgoing.len());
+        for (slot, byte) in bytes.iter_mut().zip(state.outgoing.drain(..len)) {
+            *slot = byte;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "async")]
+impl crate::async_client::AsyncNiimbotPrinterAdapter for MockAdapter {
+    async fn send(&mut self, bytes: &[u8]) -> Result<usize> {
+        NiimbotPrinterAdapter::send(self, bytes)
+    }
+
+    /// Polls the emulated printer until it has something to say.
+    async fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        loop {
+            if !self.state().outgoing.is_empty() {
+                return NiimbotPrinterAdapter::recv(self, bytes);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+    }
+}
@@ -0,0 +1,123 @@
+/// Which start print / page size packets a printer expects, named after NiimBlue's print tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintTaskVersion {
+    /// Start print is a single byte, the page size only carries rows and columns and the copies
+    /// are sent separately with a quantity packet.
+    V1,
+    /// Start print is a single byte and the page size carries rows, columns and copies.
+    V3,
+    /// Start print carries the total page count, the page size carries rows, columns and copies.
+    V4,
+}
+
+/// Which way the label comes out of the printer relative to the printhead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintDirection {
+    /// The printhead runs along the label's height, the image has to be rotated by 90 degrees.
+    Left,
+    /// The printhead runs along the label's width, the image is sent as is.
+    Top,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrinterModel {
+    D11,
+    D110,
+    B21,
+    B1,
+    B18,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelProfile {
+    pub model: PrinterModel,
+    pub name: &'static str,
+    /// Values reported by the device type info key.
+    pub device_types: &'static [u16],
+    /// Product ids under the 0x3513 vendor id.
+    pub usb_product_ids: &'static [u16],
+    pub printhead_pixels: usize,
+    pub max_density: u8,
+    pub default_density: u8,
+    pub print_task: PrintTaskVersion,
+    pub print_direction: PrintDirection,
+}
+
+pub const MODELS: &[ModelProfile] = &[
+    ModelProfile {
+        model: PrinterModel::D11,
+        name: "D11",
+        device_types: &[512, 513, 514],
+        usb_product_ids: &[],
+        printhead_pixels: 96,
+        max_density: 3,
+        default_density: 2,
+        print_task: PrintTaskVersion::V1,
+        print_direction: PrintDirection::Left,
+    },
+    ModelProfile {
+        model: PrinterModel::D110,
+        name: "D110",
+        device_types: &[2304, 2305],
+        usb_product_ids: &[],
+        printhead_pixels: 96,
+        max_density: 3,
+        default_density: 2,
+        print_task: PrintTaskVersion::V3,
+        print_direction: PrintDirection::Left,
+    },
+    ModelProfile {
+        model: PrinterModel::B21,
+        name: "B21",
+        device_types: &[768, 769, 770, 771],
+        usb_product_ids: &[],
+        printhead_pixels: 384,
+        max_density: 5,
+        default_density: 3,
+        print_task: PrintTaskVersion::V1,
+        print_direction: PrintDirection::Left,
+    },
+    ModelProfile {
+        model: PrinterModel::B1,
+        name: "B1",
+        device_types: &[4096],
+        // NIIMBOT B1: 3513:0002
+        usb_product_ids: &[0x0002],
+        printhead_pixels: 384,
+        max_density: 5,
+        default_density: 3,
+        print_task: PrintTaskVersion::V4,
+        print_direction: PrintDirection::Top,
+    },
+    ModelProfile {
+        model: PrinterModel::B18,
+        name: "B18",
+        device_types: &[3584],
+        usb_product_ids: &[],
+        printhead_pixels: 120,
+        max_density: 3,
+        default_density: 2,
+        print_task: PrintTaskVersion::V4,
+        print_direction: PrintDirection::Top,
+    },
+];
+
+impl PrinterModel {
+    pub fn profile(self) -> &'static ModelProfile {
+        MODELS.iter().find(|p| p.model == self).unwrap()
+    }
+
+    pub fn from_device_type(device_type: u16) -> Option<Self> {
+        MODELS
+            .iter()
+            .find(|p| p.device_types.contains(&device_type))
+            .map(|p| p.model)
+    }
+
+    pub fn from_usb_product_id(product_id: u16) -> Option<Self> {
+        MODELS
+            .iter()
+            .find(|p| p.usb_product_ids.contains(&product_id))
+            .map(|p| p.model)
+    }
+}
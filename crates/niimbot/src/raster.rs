@@ -0,0 +1,194 @@
+/// Clockwise rotation applied to a framebuffer before it is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Half,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// `None` for anything but 0, 90, 180 and 270.
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Half),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    fn quarter_turns(self) -> u8 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Half => 2,
+            Rotation::Clockwise270 => 3,
+        }
+    }
+
+    /// This rotation followed by `next`.
+    pub fn then(self, next: Rotation) -> Self {
+        match (self.quarter_turns() + next.quarter_turns()) % 4 {
+            0 => Rotation::None,
+            1 => Rotation::Clockwise90,
+            2 => Rotation::Half,
+            _ => Rotation::Clockwise270,
+        }
+    }
+
+    /// Width and height of a `width` by `height` image after rotating it.
+    pub fn size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Rotation::None | Rotation::Half => (width, height),
+            Rotation::Clockwise90 | Rotation::Clockwise270 => (height, width),
+        }
+    }
+}
+
+/// Rotate a row major framebuffer, returns the new pixels, width and height.
+pub fn rotate(
+    image: &[u32],
+    width: usize,
+    height: usize,
+    rotation: Rotation,
+) -> (Vec<u32>, usize, usize) {
+    let (new_width, new_height) = rotation.size(width, height);
+    if rotation == Rotation::None {
+        return (image.to_vec(), new_width, new_height);
+    }
+
+    let mut rotated = vec![0; image.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (new_x, new_y) = match rotation {
+                Rotation::None => (x, y),
+                Rotation::Clockwise90 => (height - 1 - y, x),
+                Rotation::Half => (width - 1 - x, height - 1 - y),
+                Rotation::Clockwise270 => (y, width - 1 - x),
+            };
+            rotated[new_y * new_width + new_x] = image[y * width + x];
+        }
+    }
+    (rotated, new_width, new_height)
+}
+
+/// Pad every row with white up to a multiple of 8 pixels, rows are sent as whole bytes.
+/// Returns the new pixels and width.
+pub fn pad_rows(image: Vec<u32>, width: usize, height: usize) -> (Vec<u32>, usize) {
+    let padded = width.next_multiple_of(8);
+    if padded == width {
+        return (image, width);
+    }
+    let mut out = vec![u32::MAX; padded * height];
+    for (row, pixels) in image.chunks_exact(width).enumerate() {
+        out[row * padded..row * padded + width].copy_from_slice(pixels);
+    }
+    (out, padded)
+}
+
+/// How a grayscale or color framebuffer is turned into black and white dots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dithering {
+    /// Pixels darker than the level are black.
+    Threshold(u8),
+    /// Error diffusion over the four next pixels, smooth gradients but can smear fine lines.
+    FloydSteinberg,
+    /// Error diffusion that only passes on 3/4 of the error, keeps more contrast than
+    /// Floyd–Steinberg.
+    Atkinson,
+    /// Ordered dithering with a 4x4 Bayer matrix, a regular pattern that survives the printhead
+    /// better than diffusion noise.
+    Bayer,
+}
+
+impl Dithering {
+    /// Parse `threshold`, `threshold:<level>`, `floyd-steinberg`, `atkinson` or `bayer`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.split_once(':') {
+            Some(("threshold", level)) => level.parse().ok().map(Dithering::Threshold),
+            Some(_) => None,
+            None => match name {
+                "threshold" => Some(Dithering::Threshold(128)),
+                "floyd-steinberg" => Some(Dithering::FloydSteinberg),
+                "atkinson" => Some(Dithering::Atkinson),
+                "bayer" => Some(Dithering::Bayer),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Perceived brightness of a 0RGB framebuffer pixel, 0 is black.
+pub fn luminance(pixel: u32) -> u8 {
+    let [_, r, g, b] = pixel.to_be_bytes();
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+/// Framebuffer pixel for a gray level, 255 is the same white as `u32::MAX`.
+pub fn gray(level: u8) -> u32 {
+    if level == u8::MAX {
+        return u32::MAX;
+    }
+    u32::from_be_bytes([0, level, level, level])
+}
+
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Turn any framebuffer into pure black (`0`) and white (`u32::MAX`) pixels, ready for the
+/// encoders.
+pub fn dither(image: &[u32], width: usize, height: usize, dithering: Dithering) -> Vec<u32> {
+    let pixel = |black: bool| if black { 0 } else { u32::MAX };
+
+    // (dx, dy, weight) of the neighbours the error is spread over, and what the weights add up to
+    let (diffusion, divisor): (&[(isize, usize, i32)], i32) = match dithering {
+        Dithering::Threshold(level) => {
+            return image.iter().map(|&p| pixel(luminance(p) < level)).collect();
+        }
+        Dithering::Bayer => {
+            return image
+                .iter()
+                .enumerate()
+                .map(|(i, &p)| {
+                    let (x, y) = (i % width, i / width);
+                    let level = BAYER[y % 4][x % 4] as u32 * 16 + 8;
+                    pixel((luminance(p) as u32) < level)
+                })
+                .collect();
+        }
+        Dithering::FloydSteinberg => (&[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)], 16),
+        Dithering::Atkinson => (
+            &[
+                (1, 0, 1),
+                (2, 0, 1),
+                (-1, 1, 1),
+                (0, 1, 1),
+                (1, 1, 1),
+                (0, 2, 1),
+            ],
+            8,
+        ),
+    };
+
+    let mut levels: Vec<i32> = image.iter().map(|&p| luminance(p) as i32).collect();
+    let mut out = vec![u32::MAX; image.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let black = levels[i] < 128;
+            out[i] = pixel(black);
+            let error = levels[i] - if black { 0 } else { 255 };
+
+            for &(dx, dy, weight) in diffusion {
+                let (nx, ny) = (x as isize + dx, y + dy);
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    continue;
+                }
+                levels[ny * width + nx as usize] += error * weight / divisor;
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// How often and how patiently the client reads again when a reply hasn't arrived yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Reads tried before a request counts as unanswered.
+    pub attempts: u32,
+    /// Wait after the first read that came back without the reply.
+    pub backoff: Duration,
+    /// Every further wait is this many times longer, 1.0 keeps waiting the same.
+    pub backoff_factor: f64,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            backoff: Duration::from_millis(200),
+            backoff_factor: 1.0,
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after the given failed read, starting at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.backoff_factor.powi(attempt.saturating_sub(1) as i32);
+        self.backoff.mul_f64(factor).min(self.max_backoff)
+    }
+}
+
+/// Timeouts of the client and its adapter, USB wants short ones while slow serial links or a
+/// bridge over wifi need more slack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Read and write timeout of the adapter itself, e.g. the USB bulk transfer timeout.
+    pub adapter: Duration,
+    /// Longest a request waits for its reply over all retries.
+    pub command: Duration,
+    /// Pause after every packet, printers drop packets that come in too quickly.
+    pub send_delay: Duration,
+    /// Wait between print status requests while a label prints.
+    pub status_poll: Duration,
+    /// How often an idle printer should get a heartbeat to keep it awake.
+    pub heartbeat_interval: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            adapter: Duration::from_secs(1),
+            command: Duration::from_secs(10),
+            send_delay: Duration::from_millis(10),
+            status_poll: Duration::from_millis(100),
+            heartbeat_interval: Duration::from_secs(15),
+        }
+    }
+}
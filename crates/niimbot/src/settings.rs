@@ -0,0 +1,53 @@
+use crate::{NiimbotPrinterClient, Result};
+
+/// Sounds that can be switched with the 0x58 sound settings command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SoundType {
+    BluetoothConnection = 1,
+    Power = 2,
+}
+
+/// Device settings the [`crate::supervisor::Supervisor`] applies every time a printer connects,
+/// `None` leaves a setting the way the printer has it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrinterSettings {
+    /// Density the printer uses when a print doesn't set one.
+    pub density: Option<u8>,
+    pub speed: Option<u8>,
+    /// Switches the connection and power sounds together.
+    pub sound: Option<bool>,
+    /// Run label positioning calibration for this label type, the printer feeds a label or two
+    /// to find the gaps.
+    pub calibrate: Option<u8>,
+}
+
+impl PrinterSettings {
+    /// Apply everything that is set. Settings the printer refuses or doesn't know are logged and
+    /// skipped, only a broken connection is an error.
+    pub fn apply(&self, client: &mut NiimbotPrinterClient) -> Result<()> {
+        let skip_refused = |result: Result<()>, setting: &str| match result {
+            Err(e) if !e.needs_reconnect() => {
+                log::warn!("Printer refused {setting}: {e}");
+                Ok(())
+            }
+            result => result,
+        };
+
+        if let Some(density) = self.density {
+            skip_refused(client.set_density(density), "density")?;
+        }
+        if let Some(speed) = self.speed {
+            skip_refused(client.set_speed(speed), "speed")?;
+        }
+        if let Some(enabled) = self.sound {
+            for sound in [SoundType::BluetoothConnection, SoundType::Power] {
+                skip_refused(client.set_sound(sound, enabled), "sound settings")?;
+            }
+        }
+        if let Some(label_type) = self.calibrate {
+            skip_refused(client.calibrate(label_type), "calibration")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{
+    adapters::NiimbotPrinterAdapter,
+    events::{HeartbeatStatus, PrinterEvent},
+    job::{JobOutcome, PrintJob},
+    retry::{RetryPolicy, Timeouts},
+    settings::PrinterSettings,
+    ImageEncoding, NiimbotError, NiimbotPrinterClient, Result,
+};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::sleep,
+    time::Duration,
+};
+
+/// Connection changes reported by the [`Supervisor`] through [`PrinterEvent::Connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// Connecting failed or the printer went away, the next attempt follows after `retry_in`.
+    Reconnecting {
+        attempt: u32,
+        retry_in: Duration,
+    },
+    /// Every attempt failed, the supervisor stops trying until it is used again.
+    GaveUp,
+}
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(30),
+            attempts: 8,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay after the given failed attempt, starting at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(self.max)
+    }
+}
+
+/// How many times a queued job is retried on a fresh connection before it is dropped.
+pub(crate) const JOB_ATTEMPTS: u32 = 3;
+
+type Connect = Box<dyn FnMut() -> Result<Box<dyn NiimbotPrinterAdapter>> + Send>;
+
+/// Keeps a [`NiimbotPrinterClient`] alive across printer restarts and unplugging.
+///
+/// Whenever talking to the printer fails the adapter is thrown away and `connect` is called
+/// again with [`Backoff`] in between, the autoshutdown time, image encoding, retry policy,
+/// timeouts and [`PrinterSettings`] are applied to every new connection. Print jobs wait in a
+/// queue and stay there until they printed, so a job that failed halfway is printed again once
+/// the printer is back.
+pub struct Supervisor {
+    connect: Connect,
+    client: Option<(NiimbotPrinterClient, Receiver<PrinterEvent>)>,
+    pub backoff: Backoff,
+    pub autoshutdown_time: Option<u8>,
+    pub image_encoding: ImageEncoding,
+    pub retry_policy: RetryPolicy,
+    pub timeouts: Timeouts,
+    pub settings: PrinterSettings,
+    queue: VecDeque<(PrintJob, u32)>,
+    subscribers: Vec<Sender<PrinterEvent>>,
+}
+
+impl Supervisor {
+    pub fn new(
+        connect: impl FnMut() -> Result<Box<dyn NiimbotPrinterAdapter>> + Send + 'static,
+    ) -> Self {
+        Self {
+            connect: Box::new(connect),
+            client: None,
+            backoff: Backoff::default(),
+            autoshutdown_time: None,
+            image_encoding: ImageEncoding::default(),
+            retry_policy: RetryPolicy::default(),
+            timeouts: Timeouts::default(),
+            settings: PrinterSettings::default(),
+            queue: VecDeque::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Events of whichever client is connected plus [`PrinterEvent::Connection`] changes.
+    pub fn subscribe(&mut self) -> Receiver<PrinterEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    fn emit(&mut self, event: PrinterEvent) {
+        self.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    /// Pass on what the current client reported since the last call.
+    fn forward_events(&mut self) {
+        let events: Vec<_> = match &self.client {
+            Some((_, events)) => events.try_iter().collect(),
+            None => return,
+        };
+        for event in events {
+            self.emit(event);
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn open(&mut self) -> Result<(NiimbotPrinterClient, Receiver<PrinterEvent>)> {
+        let mut client = NiimbotPrinterClient::new((self.connect)()?)?;
+        client.retry_policy = self.retry_policy;
+        client.set_timeouts(self.timeouts)?;
+        // subscribe first so the lid and paper state of the first heartbeat gets through
+        let events = client.subscribe();
+        client.heartbeat()?;
+        client.image_encoding = self.image_encoding;
+        if client.model.is_none() {
+            match client.detect_model() {
+                Ok(model) => log::info!("Detected printer model {model:?}"),
+                Err(e) => log::warn!("Could not detect printer model, using B1: {e:?}"),
+            }
+        }
+        if let Some(time) = self.autoshutdown_time {
+            client.set_autoshutdown_time(time)?;
+        }
+        self.settings.apply(&mut client)?;
+        Ok((client, events))
+    }
+
+    /// The connected client, connecting first when needed.
+    pub fn client(&mut self) -> Result<&mut NiimbotPrinterClient> {
+        if self.client.is_none() {
+            self.reconnect()?;
+        }
+        let (client, _) = self.client.as_mut().expect("just connected");
+        Ok(client)
+    }
+
+    /// Drop the current connection and connect again, backing off between attempts.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.forward_events();
+        self.client = None;
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.open() {
+                Ok(client) => {
+                    self.client = Some(client);
+                    log::info!("Printer connected");
+                    self.emit(PrinterEvent::Connection(ConnectionState::Connected));
+                    return Ok(());
+                }
+                Err(e) if attempt >= self.backoff.attempts => {
+                    log::error!("Giving up on the printer after {attempt} attempts: {e:?}");
+                    self.emit(PrinterEvent::Connection(ConnectionState::GaveUp));
+                    return Err(NiimbotError::Unreachable {
+                        attempts: attempt,
+                        source: Box::new(e),
+                    });
+                }
+                Err(e) => {
+                    let retry_in = self.backoff.delay(attempt);
+                    log::warn!("Connecting attempt {attempt} failed, retry in {retry_in:?}: {e:?}");
+                    self.emit(PrinterEvent::Connection(ConnectionState::Reconnecting {
+                        attempt,
+                        retry_in,
+                    }));
+                    sleep(retry_in);
+                }
+            }
+        }
+    }
+
+    /// Heartbeat the printer, a printer that stopped answering is reconnected.
+    pub fn heartbeat(&mut self) -> Result<HeartbeatStatus> {
+        let result = self.client()?.heartbeat();
+        self.forward_events();
+        match result {
+            Ok(status) => Ok(status),
+            Err(e) if !e.needs_reconnect() => Err(e),
+            Err(e) => {
+                log::warn!("Heartbeat failed, reconnecting: {e:?}");
+                self.reconnect()?;
+                let status = self.client()?.heartbeat();
+                self.forward_events();
+                status
+            }
+        }
+    }
+
+    pub fn enqueue(&mut self, job: PrintJob) {
+        self.queue.push_back((job, 0));
+    }
+
+    /// Jobs that have not printed yet.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Print the next queued job, `None` when the queue is empty or the job has to be retried.
+    ///
+    /// A job that fails stays queued for the next call, the printer is reconnected first when
+    /// the connection broke. After [`JOB_ATTEMPTS`] tries the job is dropped and
+    /// [`NiimbotError::JobFailed`] is returned.
+    pub fn print_next(&mut self) -> Result<Option<JobOutcome>> {
+        let Some((job, attempts)) = self.queue.front_mut() else {
+            return Ok(None);
+        };
+        *attempts += 1;
+        let job = job.clone();
+
+        let result = self.client()?.print(&job);
+        self.forward_events();
+        match result {
+            Ok(outcome) => {
+                self.queue.pop_front();
+                Ok(Some(outcome))
+            }
+            Err(e) => {
+                let attempts = self.queue.front().map_or(0, |(_, attempts)| *attempts);
+                if attempts >= JOB_ATTEMPTS {
+                    self.queue.pop_front();
+                    return Err(NiimbotError::JobFailed {
+                        attempts,
+                        source: Box::new(e),
+                    });
+                }
+                if e.needs_reconnect() {
+                    log::warn!("Print failed, reconnecting and trying again: {e:?}");
+                    self.reconnect()?;
+                } else {
+                    log::warn!("Print failed, trying again: {e:?}");
+                }
+                Ok(None)
+            }
+        }
+    }
+}
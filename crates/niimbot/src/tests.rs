@@ -0,0 +1,1069 @@
+use color_eyre::Result;
+use std::time::{Duration, Instant};
+
+use crate::{
+    adapters,
+    decoder::DecodedImage,
+    events::PrinterEvent,
+    framer::{FrameError, PacketFramer},
+    info::{InfoKey, PrintStatus, RfidInfo},
+    job::{JobOutcome, PrintJob},
+    mock::{MockAdapter, MockFault},
+    models::{PrintDirection, PrintTaskVersion, PrinterModel},
+    raster::{dither, gray, luminance, rotate, Dithering, Rotation},
+    retry::{RetryPolicy, Timeouts},
+    settings::{PrinterSettings, SoundType},
+    supervisor::{Backoff, ConnectionState, Supervisor, JOB_ATTEMPTS},
+    trace::{read_trace, Direction, ReplayAdapter, TraceAdapter},
+    ImageEncoding, NiimbotError, NiimbotPacket, NiimbotPrinterClient,
+};
+
+/// The mock answers right away, no need to wait on it like on a real printer.
+const FAST_RETRIES: RetryPolicy = RetryPolicy {
+    attempts: 5,
+    backoff: Duration::from_millis(1),
+    backoff_factor: 1.0,
+    max_backoff: Duration::from_millis(1),
+};
+
+const FAST_TIMEOUTS: Timeouts = Timeouts {
+    adapter: Duration::from_millis(50),
+    command: Duration::from_millis(100),
+    send_delay: Duration::ZERO,
+    status_poll: Duration::from_millis(1),
+    heartbeat_interval: Duration::from_millis(10),
+};
+
+fn fast_client(adapter: Box<dyn adapters::NiimbotPrinterAdapter>) -> Result<NiimbotPrinterClient> {
+    let mut client = NiimbotPrinterClient::new(adapter)?;
+    client.retry_policy = FAST_RETRIES;
+    client.set_timeouts(FAST_TIMEOUTS)?;
+    Ok(client)
+}
+
+fn mock_client() -> Result<(MockAdapter, NiimbotPrinterClient)> {
+    let adapter = MockAdapter::new();
+    let client = fast_client(Box::new(adapter.clone()))?;
+    Ok((adapter, client))
+}
+
+#[test]
+fn test_heartbeat() -> Result<()> {
+    let (_, mut printer) = mock_client()?;
+    printer.heartbeat()?;
+    Ok(())
+}
+
+#[test]
+fn test_shutdowns() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    printer.set_autoshutdown_time(3)?;
+    assert_eq!(adapter.state().autoshutdown_time, 3);
+    Ok(())
+}
+
+#[test]
+fn test_settings() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    printer.set_density(4)?;
+    printer.set_speed(2)?;
+    assert_eq!((printer.get_density()?, printer.get_speed()?), (4, 2));
+
+    printer.set_sound(SoundType::Power, true)?;
+    assert!(printer.get_sound(SoundType::Power)?);
+    assert!(!printer.get_sound(SoundType::BluetoothConnection)?);
+    printer.set_sound(SoundType::Power, false)?;
+    assert!(!printer.get_sound(SoundType::Power)?);
+
+    printer.calibrate(2)?;
+    assert_eq!(adapter.state().calibrated, Some(2));
+    Ok(())
+}
+
+#[test]
+fn test_supervisor_applies_settings() -> Result<()> {
+    let (adapter, mut supervisor) = mock_supervisor(0);
+    supervisor.settings = PrinterSettings {
+        density: Some(3),
+        speed: None,
+        sound: Some(true),
+        calibrate: Some(1),
+    };
+    // a printer without sound settings still connects, the rest is applied anyway
+    adapter.state().unsupported.push(0x58);
+    supervisor.heartbeat()?;
+
+    let state = adapter.state();
+    assert_eq!(state.density, 3);
+    assert_eq!(state.sounds, [false, false]);
+    assert_eq!(state.calibrated, Some(1));
+    drop(state);
+
+    adapter.state().unsupported.clear();
+    supervisor.reconnect()?;
+    assert_eq!(adapter.state().sounds, [true, true]);
+    Ok(())
+}
+
+#[test]
+fn test_print_label() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    let (width, height) = (16, 4);
+    let mut image = vec![u32::MAX; width * height];
+    image[0] = 0;
+    image[width + 15] = 0;
+
+    printer.print_label(&image, width, height, 1, 1, 5)?;
+
+    let state = adapter.state();
+    assert_eq!(state.label_type, 1);
+    assert_eq!(state.density, 5);
+    assert_eq!(state.page_size, Some((4, 16, 1)));
+    // the client waited on real status replies instead of giving up on an unanswered request
+    assert!(state.received.iter().any(|p| p.packet_type == 0xa3));
+    assert_eq!((state.pages_printed, state.progress), (1, 100));
+    let rows = state.image_rows();
+    assert_eq!(rows.len(), height);
+    assert_eq!(rows[0].data[6..], [0b1000_0000, 0]);
+    assert_eq!(rows[1].data[6..], [0, 0b0000_0001]);
+    Ok(())
+}
+
+#[test]
+fn test_print_job() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    let events = printer.subscribe();
+    let (width, height) = (16, 2);
+    let mut front = vec![u32::MAX; width * height];
+    front[0] = 0;
+    let back = vec![0; width * height];
+
+    let job = PrintJob::new()
+        .page(front, width, height, 2)
+        .page(back, width, height, 1)
+        .label_type(2)
+        .density(4)
+        .speed(3);
+    assert_eq!(job.total_labels(), 3);
+    assert_eq!(printer.print(&job)?, JobOutcome::Completed);
+
+    let state = adapter.state();
+    assert_eq!((state.label_type, state.density, state.speed), (2, 4, 3));
+    assert_eq!(state.pages_printed, 3);
+    assert!(!state.printing);
+    let types: Vec<u8> = state.received.iter().map(|p| p.packet_type).collect();
+    assert_eq!(types.iter().filter(|&&t| t == 0x03).count(), 2);
+    assert_eq!(types.last(), Some(&0xf3));
+    // every page starts again at row 0
+    let pages: Vec<_> = state.received.split(|p| p.packet_type == 0xe3).collect();
+    let front = DecodedImage::decode(pages[0], width)?;
+    let back = DecodedImage::decode(pages[1], width)?;
+    assert!(front.pixel(0, 0) && !front.pixel(1, 0));
+    assert!(back.pixels.iter().all(|&black| black));
+
+    let progress: Vec<_> = events
+        .try_iter()
+        .filter_map(|event| match event {
+            PrinterEvent::PrintProgress { printed, total } => Some((printed, total)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(progress, [(1, 3), (2, 3), (3, 3)]);
+    Ok(())
+}
+
+#[test]
+fn test_print_job_cancel() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    let job = PrintJob::new().page(vec![0; 16 * 2], 16, 2, 5);
+    job.cancel_handle().cancel();
+
+    assert_eq!(printer.print(&job)?, JobOutcome::Cancelled);
+    let state = adapter.state();
+    assert!(state.cancelled && !state.printing);
+    assert!(state.image_rows().is_empty());
+    assert_eq!(state.pages_printed, 0);
+    Ok(())
+}
+
+#[test]
+fn test_rotate() {
+    // 1 2 3
+    // 4 5 6
+    let image = [1, 2, 3, 4, 5, 6];
+    assert_eq!(
+        rotate(&image, 3, 2, Rotation::Clockwise90),
+        (vec![4, 1, 5, 2, 6, 3], 2, 3)
+    );
+    assert_eq!(
+        rotate(&image, 3, 2, Rotation::Half),
+        (vec![6, 5, 4, 3, 2, 1], 3, 2)
+    );
+    assert_eq!(
+        rotate(&image, 3, 2, Rotation::Clockwise270),
+        (vec![3, 6, 2, 5, 1, 4], 2, 3)
+    );
+
+    let (mut pixels, mut width, mut height) = (image.to_vec(), 3, 2);
+    for _ in 0..4 {
+        (pixels, width, height) = rotate(&pixels, width, height, Rotation::Clockwise90);
+    }
+    assert_eq!((pixels, width, height), (image.to_vec(), 3, 2));
+    assert_eq!(Rotation::from_degrees(45), None);
+}
+
+#[test]
+fn test_print_job_rotation() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    // landscape 16x8 label with the top left pixel black
+    let mut image = vec![u32::MAX; 16 * 8];
+    image[0] = 0;
+
+    let job = PrintJob::new()
+        .page(image, 16, 8, 1)
+        .rotation(Rotation::Clockwise90);
+    printer.print(&job)?;
+
+    let state = adapter.state();
+    assert_eq!(state.page_size, Some((16, 8, 1)));
+    let decoded = DecodedImage::decode(&state.received, 8)?;
+    assert_eq!(decoded.height, 16);
+    // the top left corner ends up top right
+    assert!(decoded.pixel(7, 0));
+    assert_eq!(decoded.pixels.iter().filter(|&&black| black).count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_dither() {
+    let (width, height) = (16, 16);
+    let black = |image: &[u32]| image.iter().filter(|&&p| p == 0).count();
+    let all = [
+        Dithering::Threshold(128),
+        Dithering::FloydSteinberg,
+        Dithering::Atkinson,
+        Dithering::Bayer,
+    ];
+
+    assert_eq!(luminance(gray(77)), 77);
+    for dithering in all {
+        // solid black and white are left alone
+        assert_eq!(black(&dither(&[0; 256], width, height, dithering)), 256);
+        assert_eq!(
+            black(&dither(&[u32::MAX; 256], width, height, dithering)),
+            0
+        );
+    }
+    // 0x0000ff is dark blue, not white
+    let blue = [0xff; 256];
+    assert_eq!(
+        black(&dither(&blue, width, height, Dithering::Threshold(128))),
+        256
+    );
+
+    let mid = vec![gray(127); width * height];
+    assert_eq!(
+        black(&dither(&mid, width, height, Dithering::Threshold(128))),
+        256
+    );
+    assert_eq!(
+        black(&dither(&mid, width, height, Dithering::Threshold(100))),
+        0
+    );
+    assert_eq!(black(&dither(&mid, width, height, Dithering::Bayer)), 128);
+    let diffused = black(&dither(&mid, width, height, Dithering::FloydSteinberg));
+    assert!((118..=138).contains(&diffused), "{diffused}");
+    // atkinson drops a quarter of the error so mid gray comes out lighter than half
+    let atkinson = black(&dither(&mid, width, height, Dithering::Atkinson));
+    assert!((64..=160).contains(&atkinson), "{atkinson}");
+
+    assert_eq!(Dithering::from_name("bayer"), Some(Dithering::Bayer));
+    assert_eq!(
+        Dithering::from_name("threshold:90"),
+        Some(Dithering::Threshold(90))
+    );
+    assert_eq!(Dithering::from_name("threshold:lots"), None);
+}
+
+#[test]
+fn test_print_job_dithering() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    let image = vec![gray(60); 16];
+
+    printer.print(
+        &PrintJob::new()
+            .page(image, 16, 1, 1)
+            .dithering(Dithering::Threshold(128)),
+    )?;
+
+    let decoded = DecodedImage::decode(&adapter.state().received, 16)?;
+    assert!(decoded.pixels.iter().all(|&black| black));
+    Ok(())
+}
+
+#[test]
+fn test_recv_timeout_is_retried() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    adapter.inject(MockFault::Timeout);
+    printer.heartbeat()?;
+    Ok(())
+}
+
+#[test]
+fn test_dropped_reply() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    adapter.inject(MockFault::DropReply);
+    assert!(matches!(
+        printer.heartbeat(),
+        Err(NiimbotError::Timeout { request: 0xdc })
+    ));
+    // the printer is still there, the next heartbeat goes through
+    printer.heartbeat()?;
+    Ok(())
+}
+
+#[test]
+fn test_retry_delay() {
+    let policy = RetryPolicy {
+        attempts: 6,
+        backoff: Duration::from_millis(100),
+        backoff_factor: 2.0,
+        max_backoff: Duration::from_millis(500),
+    };
+    let delays: Vec<_> = (1..=5).map(|a| policy.delay(a).as_millis()).collect();
+    assert_eq!(delays, [100, 200, 400, 500, 500]);
+    // the default waits the same every time
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.delay(1), policy.delay(5));
+}
+
+#[test]
+fn test_command_timeout() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    printer.retry_policy = RetryPolicy {
+        attempts: 100,
+        backoff: Duration::from_millis(10),
+        backoff_factor: 1.0,
+        max_backoff: Duration::from_millis(10),
+    };
+    adapter.inject(MockFault::DropReply);
+    let started = Instant::now();
+    assert!(printer.heartbeat().is_err());
+    // gives up once the command timeout is used up instead of trying a hundred times
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+    Ok(())
+}
+
+#[test]
+fn test_bad_checksum() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    adapter.inject(MockFault::BadChecksum);
+    assert!(matches!(
+        printer.heartbeat(),
+        Err(NiimbotError::Frame(FrameError::InvalidChecksum {
+            packet_type: 0xdd,
+            ..
+        }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_printer_errors() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    adapter.inject(MockFault::PrinterError(0x06));
+    assert!(matches!(
+        printer.set_autoshutdown_time(2),
+        Err(NiimbotError::Printer {
+            request: 0x27,
+            code: 0x06
+        })
+    ));
+    adapter.state().unsupported.push(0x40);
+    let error = printer.get_info(InfoKey::Speed).unwrap_err();
+    assert!(matches!(error, NiimbotError::Unsupported { request: 0x40 }));
+    assert!(!error.needs_reconnect());
+
+    adapter.state().disconnected = true;
+    let error = printer.heartbeat().unwrap_err();
+    assert!(matches!(error, NiimbotError::Disconnected));
+    assert!(error.needs_reconnect());
+
+    assert!(matches!(
+        NiimbotPacket::from_bytes(&[0x55, 0x55, 0xdc, 0x02, 0x01, 0xdf, 0xaa, 0xaa]),
+        Err(FrameError::InvalidLength {
+            packet_type: 0xdc,
+            expected: 9,
+            actual: 8
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_decode_round_trip() -> Result<()> {
+    let (width, height) = (32, 8);
+    let image: Vec<u32> = (0..width * height)
+        .map(|i| {
+            if (i * 7 + i / width) % 5 == 0 {
+                0
+            } else {
+                u32::MAX
+            }
+        })
+        .collect();
+
+    let packets = NiimbotPrinterClient::naive_encoder(width, height, &image);
+    let decoded = DecodedImage::decode(&packets, width)?;
+
+    assert_eq!(decoded.height, height);
+    assert!(decoded.diff(&image).is_empty());
+    assert_eq!(decoded.to_framebuffer(), image);
+    for row in &decoded.rows {
+        assert_eq!((row.left, row.right), row.black_pixels(width));
+        assert_eq!(row.repeat, 1);
+    }
+
+    // rows the printer never got still show up in the diff
+    let truncated = DecodedImage::decode(&packets[..height - 1], width)?;
+    assert_eq!(truncated.diff(&image).len(), width);
+    Ok(())
+}
+
+#[test]
+fn test_decode_mock_capture() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    let (width, height) = (16, 3);
+    let mut image = vec![u32::MAX; width * height];
+    image[width * 2 + 3] = 0;
+
+    printer.print_label(&image, width, height, 1, 1, 5)?;
+
+    let decoded = DecodedImage::decode(&adapter.state().received, width)?;
+    assert_eq!(decoded.height, height);
+    assert!(decoded.pixel(3, 2));
+    assert_eq!(decoded.rows[2].row, 2);
+    assert_eq!((decoded.rows[2].left, decoded.rows[2].right), (1, 0));
+    Ok(())
+}
+
+#[test]
+fn test_compressed_round_trip() -> Result<()> {
+    let (width, height) = (24, 600);
+    let mut image = vec![u32::MAX; width * height];
+    // a block of identical rows longer than a single 0x86 run can hold
+    for y in 10..400 {
+        image[y * width + 5] = 0;
+    }
+    image[500 * width + 20] = 0;
+
+    let packets = NiimbotPrinterClient::compressed_encoder(width, height, &image);
+    assert!(packets.len() < 10);
+
+    let decoded = DecodedImage::decode(&packets, width)?;
+    assert_eq!(decoded.height, height);
+    assert!(decoded.diff(&image).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_print_label_compressed() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    printer.image_encoding = ImageEncoding::Compressed;
+    let (width, height) = (16, 40);
+    let mut image = vec![u32::MAX; width * height];
+    image[20 * width] = 0;
+
+    printer.print_label(&image, width, height, 1, 1, 5)?;
+
+    let state = adapter.state();
+    assert_eq!(state.image_rows().len(), 3);
+    let decoded = DecodedImage::decode(&state.received, width)?;
+    assert!(decoded.diff(&image).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_printer_info() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    adapter.state().density = 3;
+
+    let info = printer.get_printer_info()?;
+    assert_eq!(info.density, Some(3));
+    assert_eq!(info.device_type, Some(4096));
+    assert_eq!(info.serial_number.as_deref(), Some("b1001234"));
+    assert_eq!(info.soft_version, Some(5.12));
+    assert_eq!(info.hard_version, Some(1.1));
+    assert_eq!(info.battery, Some(4));
+    Ok(())
+}
+
+#[test]
+fn test_print_status() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    adapter.state().printing = true;
+    adapter.state().pages_queued = 1;
+
+    assert_eq!(
+        printer.get_print_status()?,
+        PrintStatus::Printing {
+            page: 0,
+            progress1: 50,
+            progress2: 50
+        }
+    );
+    assert_eq!(printer.get_print_status()?.page(), Some(1));
+
+    adapter.inject(MockFault::DropReply);
+    assert_eq!(printer.get_print_status()?, PrintStatus::NotResponding);
+
+    // an unplugged printer is an error, not a finished print
+    adapter.state().disconnected = true;
+    assert!(printer.get_print_status().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_model_print_tasks() -> Result<()> {
+    let image = vec![u32::MAX; 16 * 2];
+    for (device_type, model, start_print) in [
+        (4096, PrinterModel::B1, vec![0, 2, 0, 0, 0, 0, 0]),
+        (2304, PrinterModel::D110, vec![1]),
+        (512, PrinterModel::D11, vec![1]),
+    ] {
+        let adapter = MockAdapter::with_device_type(device_type);
+        let mut printer = fast_client(Box::new(adapter.clone()))?;
+        assert_eq!(printer.detect_model()?, model);
+
+        printer.print_label(&image, 16, 2, 2, 1, 5)?;
+
+        let state = adapter.state();
+        assert_eq!(state.pages_printed, 2);
+        let start = state.received.iter().find(|p| p.packet_type == 0x01);
+        assert_eq!(start.map(|p| &p.data), Some(&start_print));
+        // the D11 and D110 top out at density 3
+        assert_eq!(state.density, model.profile().max_density);
+        // left feeding models get the label turned, its 2 pixel rows padded to a byte
+        let (rows, columns) = match model.profile().print_direction {
+            PrintDirection::Left => (16, 8),
+            PrintDirection::Top => (2, 16),
+        };
+        match model.profile().print_task {
+            PrintTaskVersion::V1 => {
+                assert_eq!(state.page_size, Some((rows, columns, 1)));
+                assert_eq!(state.quantity, Some(2));
+            }
+            _ => assert_eq!(state.page_size, Some((rows, columns, 2))),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_left_print_direction() -> Result<()> {
+    // 16 by 8 label with only its top left pixel black
+    let mut image = vec![u32::MAX; 16 * 8];
+    image[0] = 0;
+
+    let adapter = MockAdapter::with_device_type(2304);
+    let mut printer = fast_client(Box::new(adapter.clone()))?;
+    assert_eq!(printer.detect_model()?, PrinterModel::D110);
+    printer.print(&PrintJob::new().page(image.clone(), 16, 8, 1))?;
+
+    // turned clockwise the printhead sees 16 rows of 8 pixels, the pixel is at the end of the
+    // first row
+    let state = adapter.state();
+    assert_eq!(state.page_size, Some((16, 8, 1)));
+    let rows: Vec<_> = state
+        .received
+        .iter()
+        .filter(|p| p.packet_type == 0x85)
+        .map(|p| p.data[6..].to_vec())
+        .collect();
+    assert_eq!(rows.len(), 16);
+    assert_eq!(rows[0], [0b0000_0001]);
+    assert!(rows[1..].iter().all(|row| row == &[0]));
+    drop(state);
+
+    // a job rotated by 270 degrees comes out the way it was drawn
+    let adapter = MockAdapter::with_device_type(2304);
+    let mut printer = fast_client(Box::new(adapter.clone()))?;
+    printer.detect_model()?;
+    let job = PrintJob::new()
+        .rotation(Rotation::Clockwise270)
+        .page(image, 16, 8, 1);
+    printer.print(&job)?;
+    let state = adapter.state();
+    assert_eq!(state.page_size, Some((8, 16, 1)));
+    let first = state.received.iter().find(|p| p.packet_type == 0x85);
+    assert_eq!(first.map(|p| &p.data[6..]), Some(&[0b1000_0000, 0][..]));
+    Ok(())
+}
+
+#[test]
+fn test_rotation_then() {
+    assert_eq!(
+        Rotation::None.then(Rotation::Clockwise90),
+        Rotation::Clockwise90
+    );
+    assert_eq!(
+        Rotation::Clockwise90.then(Rotation::Clockwise90),
+        Rotation::Half
+    );
+    assert_eq!(
+        Rotation::Clockwise270.then(Rotation::Clockwise90),
+        Rotation::None
+    );
+    assert_eq!(
+        Rotation::Half.then(Rotation::Clockwise270),
+        Rotation::Clockwise90
+    );
+}
+
+#[test]
+fn test_rfid_info() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    assert_eq!(printer.get_rfid_info()?, None);
+
+    let roll = RfidInfo {
+        uuid: "0101010101010101".into(),
+        barcode: "6972842743589".into(),
+        serial: "PZ1G3140202".into(),
+        total_labels: 230,
+        used_labels: 12,
+        label_type: 1,
+    };
+    adapter.state().rfid = Some(roll.clone());
+
+    let rfid = printer.get_rfid_info()?;
+    assert_eq!(rfid, Some(roll));
+    assert_eq!(rfid.map(|r| r.remaining_labels()), Some(218));
+    Ok(())
+}
+
+/// Small xorshift generator so the stream tests are reproducible without extra dependencies.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, max: usize) -> usize {
+        (self.next() % max as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+fn drain(framer: &mut PacketFramer) -> Vec<NiimbotPacket> {
+    let mut packets = Vec::new();
+    while let Some(packet) = framer.next_packet() {
+        if let Ok(packet) = packet {
+            packets.push(packet);
+        }
+    }
+    packets
+}
+
+#[test]
+fn test_framer_payload_trailer() {
+    let packet = NiimbotPacket {
+        packet_type: 0x85,
+        data: vec![0xaa, 0xaa, 0x55, 0x55, 0xaa],
+    };
+    let mut framer = PacketFramer::new();
+    framer.push(&packet.to_bytes());
+    assert_eq!(framer.next_packet(), Some(Ok(packet)));
+    assert_eq!(framer.next_packet(), None);
+}
+
+#[test]
+fn test_framer_resync() {
+    let packet = NiimbotPacket {
+        packet_type: 0xdd,
+        data: vec![1, 2, 3],
+    };
+    let mut corrupt = packet.to_bytes();
+    corrupt[6] ^= 0xff;
+
+    let mut framer = PacketFramer::new();
+    framer.push(&[0x00, 0x12]);
+    framer.push(&corrupt);
+    framer.push(&packet.to_bytes());
+
+    assert_eq!(framer.next_packet(), Some(Err(FrameError::Garbage(2))));
+    assert!(matches!(
+        framer.next_packet(),
+        Some(Err(FrameError::InvalidChecksum { .. }))
+    ));
+    assert_eq!(framer.next_packet(), Some(Ok(packet)));
+    assert_eq!(framer.buffered(), 0);
+}
+
+#[test]
+fn test_framer_random_chunks() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..200 {
+        let packets: Vec<NiimbotPacket> = (0..rng.below(8))
+            .map(|_| {
+                let len = rng.below(64);
+                NiimbotPacket {
+                    packet_type: rng.next() as u8,
+                    data: rng.bytes(len),
+                }
+            })
+            .collect();
+
+        // garbage between packets, without 0x55 so it can't start a header by itself
+        let mut stream = Vec::new();
+        for packet in &packets {
+            let len = rng.below(6);
+            stream.extend(rng.bytes(len).into_iter().map(|b| b & 0x3f));
+            stream.extend(packet.to_bytes());
+        }
+
+        let mut framer = PacketFramer::new();
+        let mut received = Vec::new();
+        let mut rest = &stream[..];
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(rng.below(rest.len()) + 1);
+            framer.push(chunk);
+            received.extend(drain(&mut framer));
+            rest = tail;
+        }
+        assert_eq!(received, packets);
+    }
+}
+
+#[test]
+fn test_framer_random_bytes_never_panic() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..500 {
+        // bias towards the framing bytes so partial headers and trailers show up often
+        let len = rng.below(128);
+        let bytes: Vec<u8> = rng
+            .bytes(len)
+            .into_iter()
+            .map(|b| match b % 4 {
+                0 => 0x55,
+                1 => 0xaa,
+                _ => b,
+            })
+            .collect();
+
+        let _ = NiimbotPacket::from_bytes(&bytes);
+        let mut framer = PacketFramer::new();
+        for chunk in bytes.chunks(rng.below(16) + 1) {
+            framer.push(chunk);
+            drain(&mut framer);
+        }
+        assert!(framer.buffered() <= bytes.len());
+    }
+}
+
+#[test]
+fn test_printer_events() -> Result<()> {
+    let (adapter, mut printer) = mock_client()?;
+    let events = printer.subscribe();
+
+    let status = printer.heartbeat()?;
+    assert_eq!(status.lid_open, Some(false));
+    assert_eq!(
+        events.try_iter().collect::<Vec<_>>(),
+        [PrinterEvent::LidClosed, PrinterEvent::PaperLoaded]
+    );
+
+    // nothing changed, nothing to report
+    printer.heartbeat()?;
+    assert_eq!(events.try_recv().ok(), None);
+
+    adapter.state().paper_out = true;
+    adapter.push_unsolicited(NiimbotPacket {
+        packet_type: 0xd3,
+        data: vec![0x01],
+    });
+    printer.heartbeat()?;
+    assert_eq!(
+        events.try_iter().collect::<Vec<_>>(),
+        [PrinterEvent::CheckLine(vec![0x01]), PrinterEvent::PaperOut]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_tcp_bridge() -> Result<()> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let mock = MockAdapter::new();
+    let mut bridged = mock.clone();
+    let server = std::thread::spawn(move || -> crate::Result<()> {
+        let (socket, _) = listener.accept()?;
+        adapters::bridge(socket, &mut bridged)
+    });
+
+    let mut printer = fast_client(Box::new(adapters::TcpAdapter::new(address)?))?;
+    let status = printer.heartbeat()?;
+    assert_eq!(status.paper_out, Some(false));
+    printer.set_autoshutdown_time(4)?;
+    assert_eq!(mock.state().autoshutdown_time, 4);
+
+    // the bridge stops once the client hangs up
+    drop(printer);
+    server.join().unwrap()?;
+    Ok(())
+}
+
+/// A supervisor whose printer can't be reached for the first `failures` connection attempts,
+/// connecting also plugs the mock back in.
+fn mock_supervisor(failures: u32) -> (MockAdapter, Supervisor) {
+    let adapter = MockAdapter::new();
+    let mock = adapter.clone();
+    let mut failures = failures;
+    let mut supervisor = Supervisor::new(move || {
+        if failures > 0 {
+            failures -= 1;
+            return Err(NiimbotError::NotFound {
+                serial_number: None,
+            });
+        }
+        mock.state().disconnected = false;
+        Ok(Box::new(mock.clone()) as Box<dyn adapters::NiimbotPrinterAdapter>)
+    });
+    supervisor.backoff = Backoff {
+        initial: Duration::from_millis(1),
+        max: Duration::from_millis(4),
+        attempts: 3,
+    };
+    supervisor.retry_policy = FAST_RETRIES;
+    supervisor.timeouts = FAST_TIMEOUTS;
+    supervisor.autoshutdown_time = Some(4);
+    (adapter, supervisor)
+}
+
+#[test]
+fn test_supervisor_reconnects() -> Result<()> {
+    let (adapter, mut supervisor) = mock_supervisor(2);
+    let events = supervisor.subscribe();
+
+    supervisor.heartbeat()?;
+    let connection: Vec<_> = events
+        .try_iter()
+        .filter_map(|event| match event {
+            PrinterEvent::Connection(state) => Some(state),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        connection,
+        [
+            ConnectionState::Reconnecting {
+                attempt: 1,
+                retry_in: Duration::from_millis(1)
+            },
+            ConnectionState::Reconnecting {
+                attempt: 2,
+                retry_in: Duration::from_millis(2)
+            },
+            ConnectionState::Connected,
+        ]
+    );
+    assert_eq!(adapter.state().autoshutdown_time, 4);
+
+    // printer power cycled, the job fails once and prints on the new connection
+    {
+        let mut state = adapter.state();
+        state.disconnected = true;
+        state.autoshutdown_time = 0;
+    }
+    supervisor.enqueue(PrintJob::new().page(vec![0; 16], 16, 1, 1));
+    assert_eq!(supervisor.print_next()?, None);
+    assert_eq!(supervisor.queued(), 1);
+    assert_eq!(adapter.state().autoshutdown_time, 4);
+    assert_eq!(supervisor.print_next()?, Some(JobOutcome::Completed));
+    assert_eq!(supervisor.queued(), 0);
+    assert_eq!(adapter.state().pages_printed, 1);
+    Ok(())
+}
+
+#[test]
+fn test_supervisor_printer_error() -> Result<()> {
+    let (adapter, mut supervisor) = mock_supervisor(0);
+    supervisor.heartbeat()?;
+    let events = supervisor.subscribe();
+
+    // the printer refuses the job every time, the connection is fine so it is not reconnected
+    supervisor.enqueue(PrintJob::new().page(vec![0; 16], 16, 1, 1));
+    for _ in 1..JOB_ATTEMPTS {
+        adapter.inject(MockFault::PrinterError(0x02));
+        assert_eq!(supervisor.print_next()?, None);
+    }
+    adapter.inject(MockFault::PrinterError(0x02));
+    let error = supervisor.print_next().unwrap_err();
+    assert!(matches!(
+        error,
+        NiimbotError::JobFailed { attempts: 3, ref source }
+            if matches!(**source, NiimbotError::Printer { code: 0x02, .. })
+    ));
+    assert_eq!(supervisor.queued(), 0);
+    assert!(events
+        .try_iter()
+        .all(|e| !matches!(e, PrinterEvent::Connection(_))));
+    Ok(())
+}
+
+#[test]
+fn test_supervisor_gives_up() {
+    let (_, mut supervisor) = mock_supervisor(u32::MAX);
+    let events = supervisor.subscribe();
+
+    assert!(matches!(
+        supervisor.heartbeat(),
+        Err(NiimbotError::Unreachable { attempts: 3, .. })
+    ));
+    assert!(!supervisor.is_connected());
+    assert_eq!(
+        events.try_iter().last(),
+        Some(PrinterEvent::Connection(ConnectionState::GaveUp))
+    );
+}
+
+#[test]
+fn test_trace_record_and_replay() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("niimbot-trace-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mock = MockAdapter::new();
+    mock.push_unsolicited(NiimbotPacket {
+        packet_type: 0xd3,
+        data: vec![0x01],
+    });
+    let traced = TraceAdapter::to_file(Box::new(mock), &path)?;
+    let mut printer = fast_client(Box::new(traced))?;
+    let status = printer.heartbeat()?;
+    let model = printer.detect_model()?;
+    drop(printer);
+
+    let entries = read_trace(std::fs::File::open(&path)?)?;
+    let summary: Vec<_> = entries
+        .iter()
+        .map(|e| (e.direction, e.packet.packet_type))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (Direction::Sent, 0xdc),
+            (Direction::Received, 0xd3),
+            (Direction::Received, 0xdd),
+            (Direction::Sent, 0x40),
+            (Direction::Received, 0x48),
+        ]
+    );
+    assert!(entries.windows(2).all(|w| w[0].time <= w[1].time));
+    // lines survive a round trip through their text form
+    assert_eq!(
+        entries[0].to_string().parse::<crate::trace::TraceEntry>()?,
+        entries[0]
+    );
+
+    let mut replayed = fast_client(Box::new(ReplayAdapter::from_file(&path)?))?;
+    let events = replayed.subscribe();
+    assert_eq!(replayed.heartbeat()?, status);
+    assert_eq!(replayed.detect_model()?, model);
+    assert!(events
+        .try_iter()
+        .any(|e| e == PrinterEvent::CheckLine(vec![0x01])));
+    // nothing left to answer with
+    assert!(replayed.heartbeat().is_err());
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async_client() -> Result<()> {
+    use crate::async_client::AsyncNiimbotPrinterClient;
+
+    let adapter = MockAdapter::new();
+    let mut printer = AsyncNiimbotPrinterClient::new(adapter.clone());
+    printer.timeouts = FAST_TIMEOUTS;
+
+    block_on(async {
+        let status = printer.heartbeat().await?;
+        assert_eq!(status.paper_out, Some(false));
+
+        // a reply that never comes costs one timeout, not five retries
+        adapter.inject(MockFault::DropReply);
+        let started = Instant::now();
+        assert!(printer.heartbeat().await.is_err());
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        let mut image = vec![u32::MAX; 16 * 4];
+        image[0] = 0;
+        printer.print_label(&image, 16, 4, 2, 1, 5).await?;
+        Ok::<_, color_eyre::Report>(())
+    })?;
+
+    let state = adapter.state();
+    assert_eq!(state.page_size, Some((4, 16, 2)));
+    assert_eq!(state.pages_printed, 2);
+    assert!(!state.printing);
+    let decoded = DecodedImage::decode(&state.received, 16)?;
+    assert!(decoded.pixel(0, 0));
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_blocking_adapter_keeps_timed_out_reads() -> Result<()> {
+    use crate::async_client::{AsyncNiimbotPrinterAdapter, BlockingAdapter};
+
+    /// Every read takes a while and returns how many reads were started.
+    struct SlowAdapter(u8);
+
+    impl adapters::NiimbotPrinterAdapter for SlowAdapter {
+        fn send(&mut self, bytes: &[u8]) -> crate::Result<usize> {
+            Ok(bytes.len())
+        }
+
+        fn recv(&mut self, bytes: &mut [u8]) -> crate::Result<usize> {
+            self.0 += 1;
+            std::thread::sleep(Duration::from_millis(50));
+            bytes[..2].copy_from_slice(&[self.0, 0xff]);
+            Ok(2)
+        }
+    }
+
+    let mut adapter = BlockingAdapter::new(SlowAdapter(0));
+    block_on(async {
+        let mut bytes = [0u8; 2];
+        let timed_out =
+            tokio::time::timeout(Duration::from_millis(5), adapter.recv(&mut bytes)).await;
+        assert!(timed_out.is_err());
+
+        // the read that outlived the timeout is picked up, not started again
+        let mut bytes = [0u8; 1];
+        assert_eq!(adapter.recv(&mut bytes).await?, 1);
+        assert_eq!(bytes, [1]);
+        assert_eq!(adapter.recv(&mut bytes).await?, 1);
+        assert_eq!(bytes, [0xff]);
+        Ok::<_, color_eyre::Report>(())
+    })
+}
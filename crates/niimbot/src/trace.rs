@@ -0,0 +1,234 @@
+use crate::{
+    adapters::NiimbotPrinterAdapter, framer::PacketFramer, models::PrinterModel, NiimbotError,
+    NiimbotPacket, Result,
+};
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Which way a traced packet went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// One line of a trace file: `<seconds> <'>' or '<'> <packet type> <data bytes>`, all in hex
+/// except the time, e.g. `1.250 > dc 01`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    /// Seconds since the trace started.
+    pub time: f64,
+    pub direction: Direction,
+    pub packet: NiimbotPacket,
+}
+
+impl std::fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arrow = match self.direction {
+            Direction::Sent => '>',
+            Direction::Received => '<',
+        };
+        write!(
+            f,
+            "{:.3} {arrow} {:02x}",
+            self.time, self.packet.packet_type
+        )?;
+        for byte in &self.packet.data {
+            write!(f, " {byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for TraceEntry {
+    type Err = NiimbotError;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let invalid = |what: &str| NiimbotError::InvalidTrace(format!("{what} in {line:?}"));
+        let mut parts = line.split_whitespace();
+        let mut next = |what| parts.next().ok_or_else(|| invalid(&format!("No {what}")));
+        let time = next("time")?.parse().map_err(|_| invalid("Bad time"))?;
+        let direction = match next("direction")? {
+            ">" => Direction::Sent,
+            "<" => Direction::Received,
+            _ => return Err(invalid("Unknown direction")),
+        };
+        let hex = |byte| u8::from_str_radix(byte, 16).map_err(|_| invalid("Bad hex byte"));
+        let packet_type = hex(next("packet type")?)?;
+        let data = parts.map(hex).collect::<Result<_>>()?;
+        Ok(Self {
+            time,
+            direction,
+            packet: NiimbotPacket { packet_type, data },
+        })
+    }
+}
+
+/// Read all entries of a trace, blank lines and lines starting with `#` are skipped.
+pub fn read_trace(reader: impl Read) -> Result<Vec<TraceEntry>> {
+    let mut entries = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        entries.push(line.parse()?);
+    }
+    Ok(entries)
+}
+
+/// Wraps another adapter and writes every packet going through it to a trace, see
+/// [`TraceEntry`] for the format and [`ReplayAdapter`] to play it back.
+pub struct TraceAdapter {
+    inner: Box<dyn NiimbotPrinterAdapter>,
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+    sent: PacketFramer,
+    received: PacketFramer,
+}
+
+impl TraceAdapter {
+    pub fn new(inner: Box<dyn NiimbotPrinterAdapter>, writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            inner,
+            writer,
+            start: Instant::now(),
+            sent: PacketFramer::new(),
+            received: PacketFramer::new(),
+        }
+    }
+
+    /// Append the trace to a file, a line marks where every new trace starts.
+    pub fn to_file(inner: Box<dyn NiimbotPrinterAdapter>, path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "# trace started")?;
+        Ok(Self::new(inner, Box::new(file)))
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) -> Result<()> {
+        let framer = match direction {
+            Direction::Sent => &mut self.sent,
+            Direction::Received => &mut self.received,
+        };
+        framer.push(bytes);
+
+        let mut entries = Vec::new();
+        while let Some(packet) = framer.next_packet() {
+            match packet {
+                Ok(packet) => entries.push(TraceEntry {
+                    time: self.start.elapsed().as_secs_f64(),
+                    direction,
+                    packet,
+                }),
+                Err(e) => writeln!(self.writer, "# {e}")?,
+            }
+        }
+        for entry in entries {
+            writeln!(self.writer, "{entry}")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl NiimbotPrinterAdapter for TraceAdapter {
+    fn send(&mut self, bytes: &[u8]) -> Result<usize> {
+        let sent = self.inner.send(bytes)?;
+        self.record(Direction::Sent, bytes)?;
+        Ok(sent)
+    }
+
+    fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        let len = self.inner.recv(bytes)?;
+        self.record(Direction::Received, &bytes[..len])?;
+        Ok(len)
+    }
+
+    fn model(&self) -> Option<PrinterModel> {
+        self.inner.model()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+}
+
+/// Plays a recorded trace back to a client, every packet the client sends releases the replies
+/// that followed it in the trace.
+///
+/// Sent packets that don't match the trace are logged and replayed anyway so a trace keeps
+/// working when the client changes a little.
+pub struct ReplayAdapter {
+    entries: VecDeque<TraceEntry>,
+    outgoing: VecDeque<u8>,
+}
+
+impl ReplayAdapter {
+    pub fn new(entries: Vec<TraceEntry>) -> Self {
+        let mut adapter = Self {
+            entries: entries.into(),
+            outgoing: VecDeque::new(),
+        };
+        // whatever the printer sent before the first request
+        adapter.release_replies();
+        adapter
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(read_trace(std::fs::File::open(path)?)?))
+    }
+
+    /// Packets of the trace that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn release_replies(&mut self) {
+        while let Some(entry) = self.entries.front() {
+            if entry.direction == Direction::Sent {
+                break;
+            }
+            let entry = self.entries.pop_front().expect("just peeked");
+            self.outgoing.extend(entry.packet.to_bytes());
+        }
+    }
+}
+
+impl NiimbotPrinterAdapter for ReplayAdapter {
+    fn send(&mut self, bytes: &[u8]) -> Result<usize> {
+        let packet = NiimbotPacket::from_bytes(bytes)?;
+        match self.entries.pop_front() {
+            Some(entry) if entry.packet == packet => {}
+            Some(entry) => log::warn!(
+                "Replay expected {:?} at {:.3}s but got {:?}",
+                entry.packet,
+                entry.time,
+                packet
+            ),
+            None => {
+                return Err(NiimbotError::InvalidTrace(format!(
+                    "Trace ended, nothing to answer {packet:?} with"
+                )))
+            }
+        }
+        self.release_replies();
+        Ok(bytes.len())
+    }
+
+    fn recv(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        if self.outgoing.is_empty() {
+            return Err(std::io::Error::from(ErrorKind::TimedOut).into());
+        }
+        let len = bytes.len().min(self.outgoing.len());
+        for (slot, byte) in bytes.iter_mut().zip(self.outgoing.drain(..len)) {
+            *slot = byte;
+        }
+        Ok(len)
+    }
+}
@@ -73,6 +73,10 @@ macro_rules! define_config {
                     panic!("No IRC username found");
                 }
 
+                if !matches!(self.chat_access().as_str(), "everyone" | "subscribers" | "moderators") {
+                    log::error!("Unknown chat_access {}, everyone can draw", self.chat_access());
+                }
+
                 if std::fs::metadata(&self.font_file()).is_err() {
                     log::error!("Font file not found, please check if {} exists", self.font_file());
                 }
@@ -113,6 +117,28 @@ define_config! {
     invert_overlapping_text: bool = true,
     i_like_rgb: bool = false,
     font_file: String = "Roboto-Regular.ttf".to_string(),
+    compressed_printing: bool = false,
+    label_sizes: String = String::new(),
+    label_density: f64 = 5.0,
+    printer_address: String = String::new(),
+    printer_port: String = String::new(),
+    printer_serial_number: String = String::new(),
+    rotation: f64 = 0.0,
+    dithering: String = String::new(),
+    printer_trace: String = String::new(),
+    printer_retries: f64 = 5.0,
+    printer_retry_backoff: f64 = 0.2,
+    printer_command_timeout: f64 = 10.0,
+    printer_adapter_timeout: f64 = 1.0,
+    heartbeat_interval: f64 = 15.0,
+    printer_default_density: f64 = 0.0,
+    printer_speed: f64 = 0.0,
+    printer_sound: String = String::new(),
+    printer_calibrate: bool = false,
+    chat_access: String = "everyone".to_string(),
+    cheer_bits: f64 = 0.0,
+    censor_moderators: bool = false,
+    irc_moderator: bool = false,
 }
 
 impl Config {
@@ -123,4 +149,14 @@ impl Config {
             .round()
             .clamp(0.0, 4.0) as u8
     }
+
+    /// Size of the roll with the given RFID barcode, `label_sizes` is a comma separated list of
+    /// `barcode=WIDTHxHEIGHT` entries.
+    pub fn label_size(&self, barcode: &str) -> Option<(f64, f64)> {
+        self.label_sizes().split(',').find_map(|entry| {
+            let (code, size) = entry.trim().split_once('=')?;
+            let (width, height) = size.split_once('x')?;
+            (code == barcode).then_some((width.parse().ok()?, height.parse().ok()?))
+        })
+    }
 }
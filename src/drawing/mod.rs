@@ -26,6 +26,7 @@ mod layout_paragraph;
 use ab_glyph::{point, Font, FontArc, PxScale};
 use image_webp::WebPDecoder;
 use layout_paragraph::layout_paragraph;
+use niimbot::raster::{gray, luminance};
 use tar_wasi::Archive;
 
 use crate::CONFIG;
@@ -64,6 +65,7 @@ pub fn draw_text(pixmap: &mut [u32], text: &str, size: u32, posx: u32, posy: u32
 
     let w = CONFIG.width() as u32;
     let invert_overlapping_text = CONFIG.invert_overlapping_text();
+    let shaded = !CONFIG.dithering().is_empty();
     let mut x_offset: i32 = 0;
     let mut y_offset: i32 = 0;
     for glyph in outlined {
@@ -86,6 +88,10 @@ pub fn draw_text(pixmap: &mut [u32], text: &str, size: u32, posx: u32, posy: u32
 
             let write = v > 0.5;
             if !write {
+                // anti-aliased edges are gray when printing dithers, solid pixels stay as before
+                if shaded && v > 0.0 && pixmap[pos] == u32::MAX {
+                    pixmap[pos] = gray(255 - (v * 255.0) as u8);
+                }
                 return;
             }
             if invert_overlapping_text && pixmap[pos] != u32::MIN {
@@ -164,16 +170,29 @@ fn draw_image(pixmap: &mut [u32], image: &Bytes, size: u32, posx: u32, posy: u32
 
     let pixmap_width = CONFIG.width() as u32;
     let pixmap_height = CONFIG.height() as u32;
+    let shaded = !CONFIG.dithering().is_empty();
 
     for y in 0..height {
         for x in 0..width {
             let index = (y as usize * width as usize + x as usize) * bytes_per_pixel;
             let pixel = &data[index..index + bytes_per_pixel];
-            let a = pixel[3];
+            // icons are alpha masks, images without alpha are used by their brightness
+            let level = match pixel.get(3) {
+                Some(&a) => a,
+                None => luminance(u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]])),
+            };
 
-            if a > 128 {
+            // with dithering the level is kept as gray, otherwise it is cut off at half
+            let value = if shaded {
+                if level == u8::MAX {
+                    continue;
+                }
+                gray(level)
+            } else if level > 128 {
                 continue;
-            }
+            } else {
+                u32::MIN
+            };
 
             for sy in 0..size {
                 for sx in 0..size {
@@ -182,8 +201,11 @@ fn draw_image(pixmap: &mut [u32], image: &Bytes, size: u32, posx: u32, posy: u32
 
                     if scaled_x < pixmap_width && scaled_y < pixmap_height {
                         let pos = ((scaled_y + posy) * pixmap_width + scaled_x + posx) as usize;
-                        if let Some(value) = pixmap.get_mut(pos) {
-                            *value = u32::MIN;
+                        if let Some(pixel) = pixmap.get_mut(pos) {
+                            // never lighten what is already there
+                            if luminance(value) < luminance(*pixel) {
+                                *pixel = value;
+                            }
                         }
                     }
                 }
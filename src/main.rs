@@ -7,14 +7,25 @@ use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{env, thread};
 
 use ai::text_to_data;
-use circe::Client;
-use color_eyre::eyre::anyhow;
+use circe::{commands::Command, message::Message, ratelimit::RateLimits, Client};
 use color_eyre::Result;
 use drawing::{draw_text, fallback_parser, place_item, Data};
 use humantime::format_rfc3339;
 use image_webp::{ColorType, WebPEncoder};
 use minifb::{Key, Scale, Window, WindowOptions};
-use niimbot::{get_usb_adapter, NiimbotPrinterClient};
+use niimbot::{
+    adapters::{NiimbotPrinterAdapter, SerialPortAdapter, TcpAdapter},
+    discovery::{list_printers, open_usb_printer},
+    events::PrinterEvent,
+    get_usb_adapter,
+    job::PrintJob,
+    raster::{luminance, Dithering, Rotation},
+    retry::{RetryPolicy, Timeouts},
+    settings::PrinterSettings,
+    supervisor::{ConnectionState, Supervisor},
+    trace::TraceAdapter,
+    ImageEncoding, NiimbotError,
+};
 
 mod ai;
 mod config;
@@ -38,6 +49,168 @@ enum PrinterCommand {
     Print(Vec<u32>),
 }
 
+/// The printer goes through a `niimbot-bridge` when `printer_address` is set, then
+/// `printer_port` and `printer_serial_number` pick a locally attached printer, otherwise the first
+/// USB printer is used. With `printer_trace` set all packets are appended to that file.
+fn connect_printer() -> niimbot::Result<Box<dyn NiimbotPrinterAdapter>> {
+    let address = CONFIG.printer_address();
+    let port = CONFIG.printer_port();
+    let serial_number = CONFIG.printer_serial_number();
+    let adapter: Box<dyn NiimbotPrinterAdapter> = if !address.is_empty() {
+        Box::new(TcpAdapter::new(address.as_str())?)
+    } else if !port.is_empty() {
+        Box::new(SerialPortAdapter::new(&port)?)
+    } else if !serial_number.is_empty() {
+        Box::new(open_usb_printer(Some(&serial_number))?)
+    } else {
+        Box::new(get_usb_adapter()?)
+    };
+
+    let trace = CONFIG.printer_trace();
+    if trace.is_empty() {
+        Ok(adapter)
+    } else {
+        Ok(Box::new(TraceAdapter::to_file(adapter, trace)?))
+    }
+}
+
+/// Log the printers that can be found, before connecting so USB devices can still be opened.
+fn log_printers() {
+    match list_printers() {
+        Ok(printers) => {
+            for usb in printers.usb {
+                log::info!(
+                    "Found {:?} on USB bus {} address {}, product id {:#06x}, serial number {}",
+                    usb.model,
+                    usb.bus,
+                    usb.address,
+                    usb.product_id,
+                    usb.serial_number.as_deref().unwrap_or("unknown")
+                );
+            }
+            for serial in printers.serial {
+                log::info!("Found possible printer on serial port {}", serial.port);
+            }
+        }
+        Err(e) => log::warn!("Could not list printers: {e:?}"),
+    }
+}
+
+/// Read the RFID tag of the loaded roll for the label type, rolls listed in `label_sizes` also
+/// set the label size.
+fn detect_label(printer: &mut Supervisor) -> u8 {
+    if CONFIG.disable_printer() {
+        return 1;
+    }
+
+    let rfid = printer.client().and_then(|client| client.get_rfid_info());
+
+    match rfid {
+        Ok(Some(rfid)) => {
+            log::info!(
+                "Loaded roll {} has {} of {} labels left",
+                rfid.barcode,
+                rfid.remaining_labels(),
+                rfid.total_labels
+            );
+            match CONFIG.label_size(&rfid.barcode) {
+                Some((width, height)) => {
+                    *CONFIG.width.write().unwrap() = width;
+                    *CONFIG.height.write().unwrap() = height;
+                }
+                None => log::warn!(
+                    "Roll {} is not in label_sizes, using the configured width and height",
+                    rfid.barcode
+                ),
+            }
+            rfid.label_type
+        }
+        Ok(None) => {
+            log::warn!("Loaded roll has no RFID tag, printing with label type 1");
+            1
+        }
+        Err(e) => {
+            log::warn!("Could not read the roll's RFID tag, printing with label type 1: {e:?}");
+            1
+        }
+    }
+}
+
+/// Whether a chat message may draw, `chat_access` is `everyone`, `subscribers` or `moderators`.
+/// Cheering at least `cheer_bits` bits lets anyone draw, 0 turns that off.
+fn may_draw(message: &Message, chat_access: &str, cheer_bits: u32) -> bool {
+    if cheer_bits > 0 && message.bits() >= cheer_bits {
+        return true;
+    }
+    match chat_access {
+        "moderators" => message.is_moderator(),
+        "subscribers" => message.is_subscriber() || message.is_moderator(),
+        _ => true,
+    }
+}
+
+/// Twitch's global cheermotes, a channel's own cheermotes are left on the label.
+const CHEERMOTES: &[&str] = &[
+    "4head",
+    "anon",
+    "biblethump",
+    "bday",
+    "bitboss",
+    "charity",
+    "cheer",
+    "cheerwhal",
+    "corgo",
+    "dansgame",
+    "doodlecheer",
+    "elegiggle",
+    "failfish",
+    "frankerz",
+    "goal",
+    "heyguys",
+    "holidaycheer",
+    "kappa",
+    "kreygasm",
+    "mrdestructoid",
+    "muxy",
+    "notlikethis",
+    "party",
+    "pjsalt",
+    "pride",
+    "ripcheer",
+    "scoops",
+    "seemsgood",
+    "shamrock",
+    "showlove",
+    "streamlabs",
+    "swiftrage",
+    "trihard",
+    "uni",
+    "vohiyo",
+];
+
+/// Drop cheermotes like `Cheer100` or `Kappa50` from a message that cheered `bits`, nobody wants
+/// them on the label. Only as many as the bits add up to go, words like `mp3` or `d110` stay.
+fn strip_cheers(text: &str, bits: u32) -> String {
+    if bits == 0 {
+        return text.to_string();
+    }
+    let mut left = bits;
+    text.split_whitespace()
+        .filter(|word| {
+            let name = word.trim_end_matches(|c: char| c.is_ascii_digit());
+            let amount = word[name.len()..].parse::<u32>().unwrap_or(0);
+            let cheer = amount > 0
+                && amount <= left
+                && CHEERMOTES.contains(&name.to_ascii_lowercase().as_str());
+            if cheer {
+                left -= amount;
+            }
+            !cheer
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn main() -> Result<()> {
     color_eyre::install()?;
     if env::var("RUST_LOG").is_err() {
@@ -49,26 +222,12 @@ fn main() -> Result<()> {
     let running = Arc::new(AtomicBool::new(true));
 
     dbg!(&*CONFIG);
-    let width = CONFIG.width();
-    let height = CONFIG.height();
-
-    let mut window = Window::new(
-        "H",
-        width as usize,
-        height as usize,
-        WindowOptions {
-            resize: false,
-            scale: Scale::X1,
-            borderless: true,
-            ..WindowOptions::default()
-        },
-    )
-    .unwrap_or_else(|e| {
-        panic!("{}", e);
-    });
-
     let (tx, rx) = mpsc::channel::<UICommand>();
     let (printer_tx, printer_rx) = mpsc::channel::<PrinterCommand>();
+    // messages from the printer thread that should be posted to chat
+    let (chat_tx, chat_rx) = mpsc::channel::<String>();
+    // the printer thread reads the loaded roll first, the window size depends on it
+    let (detected_tx, detected_rx) = mpsc::channel::<()>();
 
     let tx = Arc::new(tx);
     let tx_clone = tx.clone();
@@ -79,71 +238,158 @@ fn main() -> Result<()> {
         let tx = tx_clone;
         let mut last_hb = Instant::now();
         let mut printer_task = || {
-            let mut printer = NiimbotPrinterClient::new(Box::new(get_usb_adapter()?))?;
-            printer.heartbeat()?;
-
+            let rotation = Rotation::from_degrees(CONFIG.rotation() as u16).unwrap_or_else(|| {
+                log::warn!("rotation must be 0, 90, 180 or 270, printing without rotation");
+                Rotation::None
+            });
+            let dithering = match CONFIG.dithering().as_str() {
+                "" => None,
+                name => Dithering::from_name(name).or_else(|| {
+                    log::warn!("Unknown dithering {name}, printing without dithering");
+                    None
+                }),
+            };
+            let mut printer = Supervisor::new(connect_printer);
+            if CONFIG.compressed_printing() {
+                printer.image_encoding = ImageEncoding::Compressed;
+            }
             if CONFIG.get_shutdown_time() != 0 {
-                printer.set_autoshutdown_time(CONFIG.get_shutdown_time())?;
+                printer.autoshutdown_time = Some(CONFIG.get_shutdown_time());
             }
+            printer.retry_policy = RetryPolicy {
+                attempts: CONFIG.printer_retries().max(1.0) as u32,
+                backoff: Duration::from_secs_f64(CONFIG.printer_retry_backoff().max(0.0)),
+                ..RetryPolicy::default()
+            };
+            printer.timeouts = Timeouts {
+                adapter: Duration::from_secs_f64(CONFIG.printer_adapter_timeout().max(0.01)),
+                command: Duration::from_secs_f64(CONFIG.printer_command_timeout().max(0.01)),
+                heartbeat_interval: Duration::from_secs_f64(CONFIG.heartbeat_interval().max(1.0)),
+                ..Timeouts::default()
+            };
+            let positive = |value: f64| (value >= 1.0).then_some(value as u8);
+            printer.settings = PrinterSettings {
+                density: positive(CONFIG.printer_default_density()),
+                speed: positive(CONFIG.printer_speed()),
+                sound: match CONFIG.printer_sound().as_str() {
+                    "" => None,
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    other => {
+                        log::warn!("printer_sound should be on or off, not {other}");
+                        None
+                    }
+                },
+                calibrate: None,
+            };
 
-            let mut hb_failures = 0;
+            let events = printer.subscribe();
+            if !CONFIG.disable_printer() {
+                log_printers();
+            }
+            // connects, gives up with an error when the printer can't be found at all
+            let connected = printer.heartbeat();
+            let label_type = if connected.is_ok() {
+                detect_label(&mut printer)
+            } else {
+                1
+            };
+            detected_tx.send(()).ok();
+            connected?;
+            if CONFIG.printer_calibrate() {
+                // also applied on every reconnect from now on
+                printer.settings.calibrate = Some(label_type);
+                if let Err(e) = printer.client()?.calibrate(label_type) {
+                    log::warn!("Printer refused calibration: {e}");
+                }
+            }
+            let (mut lid_open, mut paper_out, mut reconnecting) = (false, false, false);
 
             while running_thread.load(Ordering::Relaxed) {
+                for event in events.try_iter() {
+                    let was_paused = lid_open || paper_out;
+                    match event {
+                        PrinterEvent::LidOpened => lid_open = true,
+                        PrinterEvent::LidClosed => lid_open = false,
+                        PrinterEvent::PaperOut => paper_out = true,
+                        PrinterEvent::PaperLoaded => paper_out = false,
+                        PrinterEvent::Connection(ConnectionState::Reconnecting { .. }) => {
+                            if !reconnecting {
+                                reconnecting = true;
+                                chat_tx
+                                    .send("printer went away, reconnecting".to_string())
+                                    .ok();
+                            }
+                            continue;
+                        }
+                        PrinterEvent::Connection(ConnectionState::Connected) => {
+                            if reconnecting {
+                                reconnecting = false;
+                                chat_tx.send("printer reconnected".to_string()).ok();
+                            }
+                            continue;
+                        }
+                        PrinterEvent::Connection(ConnectionState::GaveUp)
+                        | PrinterEvent::CheckLine(_)
+                        | PrinterEvent::PrintProgress { .. } => continue,
+                    }
+                    let message = match event {
+                        PrinterEvent::LidOpened => "printer lid is open, labels are on hold",
+                        PrinterEvent::PaperOut => "printer ran out of labels, labels are on hold",
+                        _ if was_paused && !lid_open && !paper_out => {
+                            "printer is back, printing again"
+                        }
+                        _ => continue,
+                    };
+                    log::warn!("{message}");
+                    chat_tx.send(message.to_string()).ok();
+                }
+                let paused = lid_open || paper_out;
+
                 let now = Instant::now();
-                if now.duration_since(last_hb) > Duration::from_secs(15) {
+                // check more often while paused so printing resumes quickly
+                let hb_interval = if paused {
+                    Duration::from_secs(2)
+                } else {
+                    printer.timeouts.heartbeat_interval
+                };
+                if now.duration_since(last_hb) > hb_interval {
                     last_hb = now;
-                    if let Err(e) = printer.heartbeat() {
-                        hb_failures += 1;
-                        log::warn!(
-                            "Failed to heartbeat printer, retrying: {e:?}, failures: {hb_failures}"
-                        );
-                        if hb_failures > 5 {
-                            log::error!("Failed to heartbeat printer, exiting");
-                            Err(anyhow!(
-                                "Failed to heartbeat printer 5 times, exiting\n{e:?}",
-                            ))?;
-                        }
-                    } else {
-                        hb_failures = 0;
+                    // reconnects on its own, only gives up once the printer stays gone
+                    match printer.heartbeat() {
+                        Err(e @ NiimbotError::Unreachable { .. }) => return Err(e.into()),
+                        Err(e) => log::warn!("Heartbeat failed: {e}"),
+                        Ok(_) => {}
                     }
                 }
 
-                if let Ok(data) = printer_rx.try_recv() {
-                    match data {
+                for command in printer_rx.try_iter() {
+                    match command {
                         PrinterCommand::Print(data) => {
-                            if let Err(printer_e) = printer.print_label(
-                                &data,
-                                CONFIG.width() as usize,
-                                CONFIG.height() as usize,
-                                1,
-                                1,
-                                5,
-                            ) {
-                                log::error!("Error printing: {:?}", printer_e);
-                                log::debug!(
-                                    "Waiting 500ms to send heartbeat to see if printer is dead"
-                                );
-                                thread::sleep(Duration::from_millis(500));
-                                match printer.heartbeat() {
-                                    Ok(_) => {
-                                        log::debug!(
-                                            "Heartbeat successful, everything might just be fine"
-                                        );
-                                    }
-                                    Err(e) => {
-                                        log::error!("Heartbeat failed: {:?}", e);
-                                        log::debug!("Retrying heartbeat in 500ms");
-                                        thread::sleep(Duration::from_millis(500));
-                                        if let Err(e) = printer.heartbeat() {
-                                            log::error!("Failed to heartbeat printer, exiting");
-                                            Err(anyhow!(
-                                                "Heartbeat failed after printing multiple times exiting\n{e:?}\n\nprinter error: {printer_e:?}",
-                                            ))?;
-                                        }
-                                    }
-                                }
+                            let mut job = PrintJob::new()
+                                .page(data, CONFIG.width() as usize, CONFIG.height() as usize, 1)
+                                .label_type(label_type)
+                                .density(CONFIG.label_density() as u8)
+                                .rotation(rotation);
+                            job.dithering = dithering;
+                            printer.enqueue(job);
+                        }
+                    }
+                }
+
+                if !paused {
+                    match printer.print_next() {
+                        Err(e @ NiimbotError::Unreachable { .. }) => return Err(e.into()),
+                        Err(NiimbotError::JobFailed { source, .. }) => {
+                            log::error!("Dropped label after it failed to print: {source}");
+                            if let NiimbotError::Printer { code, .. } = *source {
+                                chat_tx
+                                    .send(format!("printer reported error {code}, label dropped"))
+                                    .ok();
                             }
                         }
+                        Err(e) => log::error!("Error printing: {e:?}"),
+                        Ok(_) => {}
                     }
                 }
                 thread::sleep(Duration::from_millis(500));
@@ -167,6 +413,27 @@ fn main() -> Result<()> {
         }
     });
 
+    if !CONFIG.disable_printer() {
+        detected_rx.recv().ok();
+    }
+    let width = CONFIG.width();
+    let height = CONFIG.height();
+
+    let mut window = Window::new(
+        "H",
+        width as usize,
+        height as usize,
+        WindowOptions {
+            resize: false,
+            scale: Scale::X1,
+            borderless: true,
+            ..WindowOptions::default()
+        },
+    )
+    .unwrap_or_else(|e| {
+        panic!("{}", e);
+    });
+
     let tx_clone = tx.clone();
 
     let running_thread = Arc::clone(&running);
@@ -176,29 +443,69 @@ fn main() -> Result<()> {
 
         let result = || {
             let mut client = Client::new(circe::Config {
+                capabilities: vec![
+                    "twitch.tv/tags".to_string(),
+                    "twitch.tv/commands".to_string(),
+                    "twitch.tv/membership".to_string(),
+                ],
                 channels: vec![CONFIG.irc_channel()],
                 host: CONFIG.irc_host(),
+                password: Some(CONFIG.irc_token()),
                 port: CONFIG.irc_port() as u16,
+                // come back now and then to post what the printer thread has to say
+                read_timeout: Some(Duration::from_millis(500)),
+                rate_limits: if CONFIG.irc_moderator() {
+                    RateLimits::twitch_moderator()
+                } else {
+                    RateLimits::default()
+                },
                 username: CONFIG.irc_username(),
                 ..Default::default()
             })?;
 
-            client.write_command(circe::commands::Command::PASS(CONFIG.irc_token()))?;
             client.identify()?;
+            let irc_states = client.subscribe();
 
             // client.privmsg(&CONFIG.irc_channel, ":Hello, world!")?;
 
             while running_thread.load(Ordering::Relaxed) {
-                let line = match client.read() {
-                    Ok(line) => line,
-                    Err(..) => {
-                        thread::sleep(std::time::Duration::from_millis(200));
-                        continue;
+                for message in chat_rx.try_iter() {
+                    if let Err(e) = client.enqueue(Command::PRIVMSG(
+                        String::new(),
+                        CONFIG.irc_channel(),
+                        format!(":{message}"),
+                    )) {
+                        log::warn!("Not sending {message:?} to chat: {e}");
+                    }
+                }
+
+                let line = client.read_message();
+                for state in irc_states.try_iter() {
+                    log::info!("IRC connection {state:?}");
+                    if state == circe::connection::ConnectionState::GaveUp
+                        && !CONFIG.notify_url().is_empty()
+                    {
+                        ntfy::NotifyBuilder::new("Lost connection to chat, still retrying".into())
+                            .send(&CONFIG.notify_url())
+                            .ok();
                     }
+                }
+                let line = match line {
+                    Ok(line) => line,
+                    // nothing came in, or reconnecting failed and is tried again next time
+                    Err(..) => continue,
                 };
 
-                match line {
-                    circe::commands::Command::PRIVMSG(nick, channel, message) => {
+                match line.command() {
+                    Command::PRIVMSG(nick, channel, message) => {
+                        if !may_draw(&line, &CONFIG.chat_access(), CONFIG.cheer_bits() as u32) {
+                            log::info!(
+                                "PRIVMSG from {nick} ignored, chat_access is {}",
+                                CONFIG.chat_access()
+                            );
+                            continue;
+                        }
+                        let message = strip_cheers(&message, line.bits());
                         let message = message.trim();
                         let analysis = Censor::from_str(message)
                             .with_censor_threshold(Type::INAPPROPRIATE)
@@ -207,11 +514,18 @@ fn main() -> Result<()> {
                             .with_ignore_self_censoring(false)
                             .with_censor_replacement('*')
                             .analyze();
-                        if analysis.is(Type::INAPPROPRIATE) && CONFIG.censoring_enabled() {
-                            client.privmsg(
-                                &CONFIG.irc_channel(),
-                                &format!(":Hey {}, i will not print that", nick),
-                            )?;
+                        let censored = CONFIG.censoring_enabled()
+                            && (CONFIG.censor_moderators() || !line.is_moderator());
+                        if analysis.is(Type::INAPPROPRIATE) && censored {
+                            let reply =
+                                format!(":Hey {}, i will not print that", line.display_name());
+                            if let Err(e) = client.enqueue(Command::PRIVMSG(
+                                String::new(),
+                                CONFIG.irc_channel(),
+                                reply,
+                            )) {
+                                log::warn!("Not replying to {nick}: {e}");
+                            }
                             log::warn!(
                                 "PRIVMSG received from {}: {} {} is {analysis:?}, will not print",
                                 nick,
@@ -246,7 +560,7 @@ fn main() -> Result<()> {
                             tx.send(UICommand::Draw(result))?;
                         }
                     }
-                    circe::commands::Command::QUIT(message) => {
+                    Command::QUIT(message) => {
                         println!("QUIT received from {}", message);
                     }
                     _ => {}
@@ -325,8 +639,7 @@ fn main() -> Result<()> {
                                 Vec::with_capacity((width * height) as usize);
 
                             for &pixel in &label_data_clone {
-                                let value = if pixel == u32::MAX { 255 } else { 0 };
-                                img_data.push(value);
+                                img_data.push(luminance(pixel));
                             }
 
                             let now = SystemTime::now();
@@ -8,6 +8,7 @@ use color_eyre::Result;
 use niimbot::{get_usb_adapter, NiimbotPrinterClient};
 
 #[test]
+#[ignore = "needs a printer attached over USB, see the mock tests in the niimbot crate"]
 fn test_shutdowns() -> Result<()> {
     std::env::set_var("RUST_LOG", "debug");
     env_logger::init();
@@ -63,3 +64,55 @@ fn test_shutdowns() -> Result<()> {
 
 //     Ok(())
 // }
+
+#[test]
+fn test_chat_access() {
+    let parse = |line: &str| circe::message::Message::parse(line).unwrap();
+    let viewer = parse(":viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #ronni :hi 1,2");
+    let subscriber =
+        parse("@badges=subscriber/6;subscriber=1 :sub!sub@sub.tmi.twitch.tv PRIVMSG #ronni :hi");
+    let moderator =
+        parse("@badges=moderator/1;mod=1 :mod!mod@mod.tmi.twitch.tv PRIVMSG #ronni :hi");
+    let cheer = parse("@bits=100 :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #ronni :Cheer100 hi");
+
+    assert!(crate::may_draw(&viewer, "everyone", 0));
+    assert!(!crate::may_draw(&viewer, "subscribers", 0));
+    assert!(crate::may_draw(&subscriber, "subscribers", 0));
+    assert!(crate::may_draw(&moderator, "subscribers", 0));
+    assert!(!crate::may_draw(&subscriber, "moderators", 0));
+    assert!(crate::may_draw(&moderator, "moderators", 0));
+    assert!(!crate::may_draw(&cheer, "moderators", 0));
+    assert!(crate::may_draw(&cheer, "moderators", 100));
+    assert!(!crate::may_draw(&cheer, "moderators", 500));
+}
+
+#[test]
+fn test_strip_cheers() {
+    let parse = |line: &str| circe::message::Message::parse(line).unwrap();
+    let strip = |line: &str| {
+        let message = parse(line);
+        crate::strip_cheers(
+            message.trailing.as_deref().unwrap_or_default(),
+            message.bits(),
+        )
+    };
+
+    assert_eq!(
+        strip("@bits=150 :a!a@a.tmi.twitch.tv PRIVMSG #ronni :Cheer100 hello 10,20 Kappa50"),
+        "hello 10,20"
+    );
+    assert_eq!(
+        strip("@bits=50 :a!a@a.tmi.twitch.tv PRIVMSG #ronni :uni50 cat x5 mp3 d110 10,20"),
+        "cat x5 mp3 d110 10,20"
+    );
+    // more than was cheered stays
+    assert_eq!(
+        strip("@bits=100 :a!a@a.tmi.twitch.tv PRIVMSG #ronni :Cheer100 Cheer100 hi"),
+        "Cheer100 hi"
+    );
+    // without bits nothing is a cheer
+    assert_eq!(
+        strip(":a!a@a.tmi.twitch.tv PRIVMSG #ronni :Cheer100 b1 mp3 hi"),
+        "Cheer100 b1 mp3 hi"
+    );
+}